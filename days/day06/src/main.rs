@@ -1,3 +1,5 @@
+use std::{fs, io, path::Path};
+
 use common::read_input;
 
 struct Coord(usize, usize);
@@ -24,39 +26,142 @@ struct Instruction {
     to: Coord,
 }
 
-struct Grid([bool; 1_000_000]);
+trait Light: Copy + Default {
+    fn turn_on(self) -> Self;
+    fn toggle(self) -> Self;
+    fn turn_off(self) -> Self;
+
+    fn apply(self, command: &Command) -> Self {
+        match command {
+            Command::TurnOn => self.turn_on(),
+            Command::Toggle => self.toggle(),
+            Command::TurnOff => self.turn_off(),
+        }
+    }
+}
+
+impl Light for bool {
+    fn turn_on(self) -> Self {
+        true
+    }
+
+    fn toggle(self) -> Self {
+        !self
+    }
+
+    fn turn_off(self) -> Self {
+        false
+    }
+}
+
+impl Light for u32 {
+    fn turn_on(self) -> Self {
+        self + 1
+    }
 
-impl Default for Grid {
+    fn toggle(self) -> Self {
+        self + 2
+    }
+
+    fn turn_off(self) -> Self {
+        self.saturating_sub(1)
+    }
+}
+
+struct LightGrid<L: Light>(Vec<L>);
+
+type Grid = LightGrid<bool>;
+type GridV2 = LightGrid<u32>;
+
+impl<L: Light> Default for LightGrid<L> {
     fn default() -> Self {
-        let lights = [false; 1_000_000];
-        Grid(lights)
+        LightGrid(vec![L::default(); 1_000_000])
     }
 }
 
-impl Grid {
+impl<L: Light> LightGrid<L> {
     #[allow(dead_code)]
-    fn get(&self, col: usize, row: usize) -> bool {
+    fn get(&self, col: usize, row: usize) -> L {
         self.0[row * 1000 + col]
     }
 
     fn apply(&mut self, i: Instruction) {
         for row in i.from.1..=i.to.1 {
-            for col in i.from.0..=i.to.0 {
-                let light = &mut self.0[row * 1000 + col];
-                match i.command {
-                    Command::TurnOn => *light = true,
-                    Command::Toggle => *light = !*light,
-                    Command::TurnOff => *light = false,
-                }
+            for light in &mut self.0[row * 1000 + i.from.0..=row * 1000 + i.to.0] {
+                *light = light.apply(&i.command);
             }
         }
     }
+}
 
+impl Grid {
+    #[cfg(test)]
     fn count_lights(&self) -> usize {
-        self.0.iter().filter(|&l| *l == true).count()
+        self.0.iter().filter(|&l| *l).count()
+    }
+}
+
+impl GridV2 {
+    #[cfg(test)]
+    fn total_brightness(&self) -> u32 {
+        self.0.iter().sum()
+    }
+}
+
+trait Image {
+    const EXTENSION: &'static str;
+
+    fn to_image(&self) -> Vec<u8>;
+}
+
+impl Image for Grid {
+    const EXTENSION: &'static str = "pbm";
+
+    fn to_image(&self) -> Vec<u8> {
+        let mut image = b"P4\n1000 1000\n".to_vec();
+        // PBM uses 1 for black, so lit lights are written as 0 to show up white
+        self.0.chunks(1000).for_each(|row| {
+            image.extend(row.chunks(8).map(|bits| {
+                bits.iter()
+                    .enumerate()
+                    .filter(|&(_, &on)| !on)
+                    .fold(0u8, |byte, (bit, _)| byte | 0x80 >> bit)
+            }))
+        });
+        image
     }
 }
 
+impl Image for GridV2 {
+    const EXTENSION: &'static str = "pgm";
+
+    fn to_image(&self) -> Vec<u8> {
+        let max = self.0.iter().copied().max().unwrap_or(0).max(1) as u64;
+        let mut image = b"P5\n1000 1000\n255\n".to_vec();
+        image.extend(
+            self.0
+                .iter()
+                .map(|&brightness| (brightness as u64 * 255 / max) as u8),
+        );
+        image
+    }
+}
+
+fn write_frames<L: Light>(input: &str, dir: &Path) -> io::Result<LightGrid<L>>
+where
+    LightGrid<L>: Image,
+{
+    fs::create_dir_all(dir)?;
+    let digits = input.lines().count().to_string().len();
+    let mut grid = LightGrid::<L>::default();
+    for (n, line) in input.lines().enumerate() {
+        grid.apply(Instruction::from(line));
+        let name = format!("frame_{:0digits$}.{}", n + 1, LightGrid::<L>::EXTENSION);
+        fs::write(dir.join(name), grid.to_image())?;
+    }
+    Ok(grid)
+}
+
 impl From<&str> for Instruction {
     fn from(value: &str) -> Self {
         let (command, rest) = if value.starts_with("turn on") {
@@ -74,53 +179,141 @@ impl From<&str> for Instruction {
     }
 }
 
-struct GridV2(Vec<u32>);
+struct CompressedGrid<L: Light> {
+    cols: Vec<usize>,
+    rows: Vec<usize>,
+    lights: Vec<L>,
+}
 
-impl Default for GridV2 {
-    fn default() -> Self {
-        let lights = vec![0; 1_000_000];
-        GridV2(lights)
+impl<L: Light> From<&[Instruction]> for CompressedGrid<L> {
+    fn from(instructions: &[Instruction]) -> Self {
+        let axis = |bounds: Vec<usize>| {
+            let mut axis = bounds;
+            axis.sort_unstable();
+            axis.dedup();
+            axis
+        };
+        let cols = axis(
+            instructions
+                .iter()
+                .flat_map(|i| [i.from.0, i.to.0 + 1])
+                .collect(),
+        );
+        let rows = axis(
+            instructions
+                .iter()
+                .flat_map(|i| [i.from.1, i.to.1 + 1])
+                .collect(),
+        );
+        let lights =
+            vec![L::default(); cols.len().saturating_sub(1) * rows.len().saturating_sub(1)];
+        CompressedGrid { cols, rows, lights }
     }
 }
 
-impl GridV2 {
+impl<L: Light> CompressedGrid<L> {
+    fn index(axis: &[usize], coord: usize) -> usize {
+        axis.binary_search(&coord)
+            .unwrap_or_else(|_| panic!("Coordinate {coord} is not on the grid axes"))
+    }
+
     #[allow(dead_code)]
-    fn get(&self, col: usize, row: usize) -> u32 {
-        self.0[row * 1000 + col]
+    fn get(&self, col: usize, row: usize) -> L {
+        let block_col = self.cols.partition_point(|&c| c <= col);
+        let block_row = self.rows.partition_point(|&r| r <= row);
+        if block_col == 0
+            || block_col == self.cols.len()
+            || block_row == 0
+            || block_row == self.rows.len()
+        {
+            return L::default();
+        }
+        self.lights[(block_row - 1) * (self.cols.len() - 1) + block_col - 1]
     }
 
-    fn apply(&mut self, i: Instruction) {
-        for row in i.from.1..=i.to.1 {
-            for col in i.from.0..=i.to.0 {
-                let light = &mut self.0[row * 1000 + col];
-                match i.command {
-                    Command::TurnOn => *light += 1,
-                    Command::Toggle => *light += 2,
-                    Command::TurnOff => *light = if *light == 0 { 0 } else { *light - 1 },
-                }
+    fn apply(&mut self, i: &Instruction) {
+        let width = self.cols.len() - 1;
+        let (col_from, col_to) = (
+            Self::index(&self.cols, i.from.0),
+            Self::index(&self.cols, i.to.0 + 1),
+        );
+        let (row_from, row_to) = (
+            Self::index(&self.rows, i.from.1),
+            Self::index(&self.rows, i.to.1 + 1),
+        );
+        for row in row_from..row_to {
+            for light in &mut self.lights[row * width + col_from..row * width + col_to] {
+                *light = light.apply(&i.command);
             }
         }
     }
 
-    fn total_brightness(&self) -> u32 {
-        self.0.iter().sum()
+    fn sum_by(&self, value: impl Fn(L) -> u128) -> u128 {
+        let width = self.cols.len().saturating_sub(1);
+        self.lights
+            .iter()
+            .enumerate()
+            .map(|(block, &light)| {
+                let (row, col) = (block / width, block % width);
+                let width = (self.cols[col + 1] - self.cols[col]) as u128;
+                let height = (self.rows[row + 1] - self.rows[row]) as u128;
+                value(light) * width * height
+            })
+            .sum()
+    }
+}
+
+impl CompressedGrid<bool> {
+    fn count_lights(&self) -> u128 {
+        self.sum_by(|light| light as u128)
+    }
+}
+
+impl CompressedGrid<u32> {
+    fn total_brightness(&self) -> u128 {
+        self.sum_by(|light| light as u128)
     }
 }
 
+fn save_image<I: Image>(image: &I, dir: &Path, name: &str) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(
+        dir.join(format!("{name}.{}", I::EXTENSION)),
+        image.to_image(),
+    )
+}
+
 fn main() {
+    let args = std::env::args().collect::<Vec<_>>();
+    let images = args
+        .iter()
+        .position(|arg| arg == "--images")
+        .and_then(|i| args.get(i + 1))
+        .map(Path::new);
+    let frames = args.iter().any(|arg| arg == "--frames");
     let input = read_input("day06.txt");
-    let mut grid = Grid::default();
-    input.lines().for_each(|s| {
-        let instruction = Instruction::from(s);
-        grid.apply(instruction);
-    });
+    let instructions = input.lines().map(Instruction::from).collect::<Vec<_>>();
+    let mut grid = CompressedGrid::<bool>::from(instructions.as_slice());
+    instructions.iter().for_each(|i| grid.apply(i));
     println!("Part 1 = {}", grid.count_lights());
-    let mut grid = GridV2::default();
-    input.lines().for_each(|s| {
-        let instruction = Instruction::from(s);
-        grid.apply(instruction);
-    });
-    println!("Part 1 = {}", grid.total_brightness());
+    let mut grid = CompressedGrid::<u32>::from(instructions.as_slice());
+    instructions.iter().for_each(|i| grid.apply(i));
+    println!("Part 2 = {}", grid.total_brightness());
+    if let Some(dir) = images {
+        if frames {
+            let grid = write_frames::<bool>(&input, &dir.join("part1")).unwrap();
+            save_image(&grid, dir, "part1").unwrap();
+            let grid = write_frames::<u32>(&input, &dir.join("part2")).unwrap();
+            save_image(&grid, dir, "part2").unwrap();
+        } else {
+            let mut grid = Grid::default();
+            input.lines().for_each(|s| grid.apply(Instruction::from(s)));
+            save_image(&grid, dir, "part1").unwrap();
+            let mut grid = GridV2::default();
+            input.lines().for_each(|s| grid.apply(Instruction::from(s)));
+            save_image(&grid, dir, "part2").unwrap();
+        }
+    }
 }
 
 #[cfg(test)]
@@ -160,8 +353,8 @@ mod tests {
         let instruction = Instruction::from(input);
         grid.apply(instruction);
         assert_eq!(grid.count_lights(), 500);
-        assert_eq!(grid.get(0, 0), false);
-        assert_eq!(grid.get(500, 0), true);
+        assert!(!grid.get(0, 0));
+        assert!(grid.get(500, 0));
     }
 
     #[test]
@@ -177,14 +370,14 @@ mod tests {
         let instruction = Instruction::from(input);
         grid.apply(instruction);
         assert_eq!(grid.count_lights(), 999_996);
-        assert_eq!(grid.get(499, 499), false);
-        assert_eq!(grid.get(499, 500), false);
-        assert_eq!(grid.get(500, 499), false);
-        assert_eq!(grid.get(500, 500), false);
-        assert_eq!(grid.get(0, 0), true);
-        assert_eq!(grid.get(0, 999), true);
-        assert_eq!(grid.get(999, 0), true);
-        assert_eq!(grid.get(999, 999), true);
+        assert!(!grid.get(499, 499));
+        assert!(!grid.get(499, 500));
+        assert!(!grid.get(500, 499));
+        assert!(!grid.get(500, 500));
+        assert!(grid.get(0, 0));
+        assert!(grid.get(0, 999));
+        assert!(grid.get(999, 0));
+        assert!(grid.get(999, 999));
     }
 
     #[test]
@@ -206,4 +399,153 @@ mod tests {
         grid.apply(instruction);
         assert_eq!(grid.total_brightness(), 2_000_000);
     }
+
+    #[test]
+    fn day06_test_compressed_matches_grid() {
+        let input = r#"turn on 489,959 through 759,964
+turn off 820,516 through 871,914
+toggle 0,0 through 999,0
+turn on 0,0 through 499,499
+toggle 250,250 through 749,749
+turn off 499,499 through 500,500"#;
+        let mut grid = Grid::default();
+        input.lines().for_each(|s| grid.apply(Instruction::from(s)));
+        let instructions = input.lines().map(Instruction::from).collect::<Vec<_>>();
+        let mut compressed = CompressedGrid::<bool>::from(instructions.as_slice());
+        instructions.iter().for_each(|i| compressed.apply(i));
+        assert_eq!(compressed.count_lights(), grid.count_lights() as u128);
+        for (col, row) in [
+            (0, 0),
+            (499, 499),
+            (500, 0),
+            (600, 600),
+            (999, 999),
+            (760, 964),
+        ] {
+            assert_eq!(compressed.get(col, row), grid.get(col, row));
+        }
+    }
+
+    #[test]
+    fn day06_test_compressed_huge_coordinates() {
+        let input = r#"turn on 0,0 through 999999999,999999999
+toggle 1,1 through 999999998,999999998
+turn off 500000000,0 through 500000000,999999999"#;
+        let instructions = input.lines().map(Instruction::from).collect::<Vec<_>>();
+        let mut compressed = CompressedGrid::<bool>::from(instructions.as_slice());
+        instructions.iter().for_each(|i| compressed.apply(i));
+        assert_eq!(compressed.count_lights(), 4 * 999_999_999 - 2);
+        assert!(compressed.get(0, 123_456_789));
+        assert!(!compressed.get(1, 1));
+        assert!(!compressed.get(500_000_000, 0));
+        assert!(!compressed.get(1_000_000_000, 0));
+    }
+
+    #[test]
+    fn day06_test_compressed_brightness_beyond_u64() {
+        let instructions = (0..10)
+            .map(|_| Instruction::from("toggle 0,0 through 999999999,999999999"))
+            .collect::<Vec<_>>();
+        let mut compressed = CompressedGrid::<u32>::from(instructions.as_slice());
+        instructions.iter().for_each(|i| compressed.apply(i));
+        assert_eq!(compressed.total_brightness(), 20_000_000_000_000_000_000);
+        assert!(compressed.total_brightness() > u64::MAX as u128);
+    }
+
+    #[derive(Clone, Copy, Default, PartialEq, Debug)]
+    struct ToggleCount(u32);
+
+    impl Light for ToggleCount {
+        fn turn_on(self) -> Self {
+            self
+        }
+
+        fn toggle(self) -> Self {
+            ToggleCount(self.0 + 1)
+        }
+
+        fn turn_off(self) -> Self {
+            self
+        }
+    }
+
+    #[test]
+    fn day06_test_custom_semantics() {
+        let input = r#"toggle 0,0 through 9,9
+turn on 0,0 through 999,999
+toggle 5,5 through 14,14"#;
+        let mut grid = LightGrid::<ToggleCount>::default();
+        input.lines().for_each(|s| grid.apply(Instruction::from(s)));
+        assert_eq!(grid.get(0, 0), ToggleCount(1));
+        assert_eq!(grid.get(7, 7), ToggleCount(2));
+        assert_eq!(grid.get(12, 12), ToggleCount(1));
+        assert_eq!(grid.get(20, 20), ToggleCount(0));
+        let instructions = input.lines().map(Instruction::from).collect::<Vec<_>>();
+        let mut compressed = CompressedGrid::<ToggleCount>::from(instructions.as_slice());
+        instructions.iter().for_each(|i| compressed.apply(i));
+        assert_eq!(compressed.sum_by(|count| count.0 as u128), 100 + 100);
+        assert_eq!(compressed.get(7, 7), ToggleCount(2));
+    }
+
+    #[test]
+    fn day06_test_compressed_brightness() {
+        let input = r#"turn on 0,0 through 999,999
+toggle 0,0 through 999,0
+turn off 0,0 through 0,0
+turn off 0,0 through 0,0
+turn off 0,0 through 0,0
+turn off 0,0 through 0,0"#;
+        let mut grid = GridV2::default();
+        input.lines().for_each(|s| grid.apply(Instruction::from(s)));
+        let instructions = input.lines().map(Instruction::from).collect::<Vec<_>>();
+        let mut compressed = CompressedGrid::<u32>::from(instructions.as_slice());
+        instructions.iter().for_each(|i| compressed.apply(i));
+        assert_eq!(grid.get(0, 0), 0);
+        assert_eq!(compressed.get(0, 0), 0);
+        assert_eq!(grid.total_brightness(), 1_000_000 + 2000 - 3);
+        assert_eq!(compressed.total_brightness(), 1_000_000 + 2000 - 3);
+    }
+
+    #[test]
+    fn day06_test_pbm() {
+        let mut grid = Grid::default();
+        grid.apply(Instruction::from("turn on 1,0 through 8,0"));
+        grid.apply(Instruction::from("turn on 999,999 through 999,999"));
+        let image = grid.to_image();
+        let header = b"P4\n1000 1000\n";
+        assert!(image.starts_with(header));
+        assert_eq!(image.len(), header.len() + 125 * 1000);
+        assert_eq!(image[header.len()..header.len() + 3], [0x80, 0x7f, 0xff]);
+        assert_eq!(image[image.len() - 1], 0xfe);
+    }
+
+    #[test]
+    fn day06_test_pgm() {
+        let mut grid = GridV2::default();
+        assert!(grid.to_image()[17..].iter().all(|&level| level == 0));
+        grid.apply(Instruction::from("toggle 0,0 through 1,0"));
+        grid.apply(Instruction::from("toggle 0,0 through 0,0"));
+        grid.apply(Instruction::from("turn on 2,0 through 2,0"));
+        let image = grid.to_image();
+        let header = b"P5\n1000 1000\n255\n";
+        assert!(image.starts_with(header));
+        assert_eq!(image.len(), header.len() + 1_000_000);
+        assert_eq!(image[header.len()..header.len() + 4], [255, 127, 63, 0]);
+    }
+
+    #[test]
+    fn day06_test_frames() {
+        let dir = std::env::temp_dir().join(format!("day06_frames_{}", std::process::id()));
+        let input = r#"turn on 0,0 through 9,9
+toggle 5,5 through 14,14"#;
+        let grid = write_frames::<u32>(input, &dir).unwrap();
+        assert_eq!(grid.total_brightness(), 100 + 200);
+        let mut first = GridV2::default();
+        first.apply(Instruction::from("turn on 0,0 through 9,9"));
+        assert_eq!(fs::read(dir.join("frame_1.pgm")).unwrap(), first.to_image());
+        assert_eq!(fs::read(dir.join("frame_2.pgm")).unwrap(), grid.to_image());
+        write_frames::<bool>(input, &dir).unwrap();
+        assert!(dir.join("frame_2.pbm").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -1,167 +1,830 @@
-use std::isize;
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
 
 use common::read_input;
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rule {
+    birth: [bool; 9],
+    survival: [bool; 9],
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Rule::from("B3/S23")
+    }
+}
+
+impl From<&str> for Rule {
+    fn from(value: &str) -> Self {
+        let mut rule = Rule {
+            birth: [false; 9],
+            survival: [false; 9],
+        };
+        let (birth, survival) = value
+            .trim()
+            .split_once('/')
+            .unwrap_or_else(|| panic!("Invalid rulestring {value}"));
+        let counts = |part: &str, prefix: char| {
+            let part = part
+                .strip_prefix(prefix)
+                .or_else(|| part.strip_prefix(prefix.to_ascii_lowercase()))
+                .unwrap_or_else(|| panic!("Invalid rulestring {value}"));
+            part.chars()
+                .map(|c| match c.to_digit(10) {
+                    Some(n) if n <= 8 => n as usize,
+                    _ => panic!("Invalid neighbour count {c} in rulestring {value}"),
+                })
+                .collect::<Vec<_>>()
+        };
+        counts(birth, 'B')
+            .into_iter()
+            .for_each(|n| rule.birth[n] = true);
+        counts(survival, 'S')
+            .into_iter()
+            .for_each(|n| rule.survival[n] = true);
+        rule
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let counts = |set: &[bool; 9]| {
+            (0..9)
+                .filter(|&n| set[n])
+                .map(|n| n.to_string())
+                .collect::<String>()
+        };
+        write!(f, "B{}/S{}", counts(&self.birth), counts(&self.survival))
+    }
+}
+
+impl Rule {
+    #[cfg(test)]
+    fn next(&self, alive: bool, neighbors: usize) -> bool {
+        if alive {
+            self.survival[neighbors]
+        } else {
+            self.birth[neighbors]
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Boundary {
+    #[default]
+    Dead,
+    Torus,
+    Unbounded,
+}
+
+impl From<&str> for Boundary {
+    fn from(value: &str) -> Self {
+        match value {
+            "dead" => Boundary::Dead,
+            "torus" => Boundary::Torus,
+            "unbounded" => Boundary::Unbounded,
+            b => panic!("Unknown boundary {b}"),
+        }
+    }
+}
+
+#[derive(Clone)]
 struct Lights {
-    grid: Vec<bool>,
+    cells: Vec<u64>,
+    next: Vec<u64>,
     rows: usize,
     cols: usize,
-    locked_corners: bool,
+    words: usize,
+    rule: Rule,
+    pinned: HashMap<(usize, usize), bool>,
+    boundary: Boundary,
+    origin: (isize, isize),
 }
 
-impl ToString for Lights {
-    fn to_string(&self) -> String {
+impl fmt::Display for Lights {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s = (0..self.rows)
             .map(|row| {
                 let mut row = (0..self.cols)
-                    .map(|col| {
-                        let c = self.get_light(row, col);
-                        match c {
-                            true => '#',
-                            false => '.',
-                        }
-                    })
+                    .map(|col| if self.get_light(row, col) { '#' } else { '.' })
                     .collect::<String>();
                 row.push('\n');
                 row
             })
             .collect::<String>();
-        s.trim_end().to_string()
+        write!(f, "{}", s.trim_end())
     }
 }
 
 impl From<&str> for Lights {
     fn from(input: &str) -> Self {
-        let mut cols = 0;
-        let mut rows = 0;
         let grid = input
             .lines()
-            .flat_map(|row| {
-                rows += 1;
-                cols = 0;
+            .map(|row| {
                 row.trim()
                     .chars()
-                    .map(|c| {
-                        cols += 1;
-                        match c {
-                            '.' => false,
-                            '#' => true,
-                            c => panic!("Unknown character {c}"),
-                        }
+                    .map(|c| match c {
+                        '.' => false,
+                        '#' => true,
+                        c => panic!("Unknown character {c}"),
                     })
                     .collect::<Vec<bool>>()
             })
-            .collect::<Vec<bool>>();
+            .collect::<Vec<_>>();
+        let cols = grid.last().map_or(0, |row| row.len());
+        let mut lights = Lights::new(grid.len(), cols);
+        grid.iter().enumerate().for_each(|(row, lights_row)| {
+            lights_row
+                .iter()
+                .enumerate()
+                .for_each(|(col, &on)| lights.set_light(row, col, on))
+        });
+        lights
+    }
+}
+
+impl Lights {
+    fn new(rows: usize, cols: usize) -> Self {
+        let words = cols.div_ceil(64);
         Self {
-            grid,
+            cells: vec![0; rows * words],
+            next: vec![0; rows * words],
             rows,
             cols,
-            locked_corners: false,
+            words,
+            rule: Rule::default(),
+            pinned: HashMap::new(),
+            boundary: Boundary::default(),
+            origin: (0, 0),
         }
     }
-}
 
-impl Lights {
-    fn with_locked_corners(mut self) -> Self {
-        self.locked_corners = true;
+    fn with_locked_corners(self) -> Self {
+        let (last_row, last_col) = (self.rows - 1, self.cols - 1);
+        self.with_pinned(
+            &[(0, 0), (0, last_col), (last_row, 0), (last_row, last_col)],
+            true,
+        )
+    }
+
+    fn with_pinned(mut self, cells: &[(usize, usize)], on: bool) -> Self {
+        cells.iter().for_each(|&(row, col)| {
+            self.pinned.insert((row, col), on);
+            self.set_light(row, col, on);
+        });
+        self
+    }
+
+    fn with_rule(mut self, rule: Rule) -> Self {
+        self.rule = rule;
         self
     }
 
-    fn get_light(&self, row: usize, col: usize) -> &bool {
-        if self.locked_corners && {
-            (row == 0 && col == 0)
-                || (row == 0 && col == self.cols - 1)
-                || (row == self.rows - 1 && col == 0)
-                || (row == self.rows - 1 && col == self.cols - 1)
-        } {
-            &true
+    fn with_boundary(mut self, boundary: Boundary) -> Self {
+        self.boundary = boundary;
+        self
+    }
+
+    fn origin(&self) -> (isize, isize) {
+        self.origin
+    }
+
+    fn get_light(&self, row: usize, col: usize) -> bool {
+        assert!(row < self.rows && col < self.cols);
+        self.cells[row * self.words + col / 64] >> (col % 64) & 1 == 1
+    }
+
+    fn set_light(&mut self, row: usize, col: usize, on: bool) {
+        assert!(row < self.rows && col < self.cols);
+        let word = &mut self.cells[row * self.words + col / 64];
+        if on {
+            *word |= 1 << (col % 64);
         } else {
-            self.grid.get(row * self.cols + col).unwrap()
+            *word &= !(1 << (col % 64));
         }
     }
 
-    fn get_neighbors(&self, row: usize, col: usize) -> Vec<&bool> {
-        let delta_rows: Vec<isize> = if row == 0 {
-            vec![0, 1]
-        } else if row == self.rows - 1 {
-            vec![-1, 0]
-        } else {
-            vec![-1, 0, 1]
-        };
-        let delta_cols: Vec<isize> = if col == 0 {
-            vec![0, 1]
-        } else if col == self.cols - 1 {
-            vec![-1, 0]
-        } else {
-            vec![-1, 0, 1]
-        };
-        let mut delta_neighbors: Vec<(isize, isize)> = Vec::new();
-        delta_rows.iter().for_each(|&dr| {
-            delta_cols.iter().for_each(|&dc| {
-                if (dr, dc) != (0, 0) {
-                    delta_neighbors.push((dr, dc));
+    #[cfg(test)]
+    fn get_neighbors(&self, row: usize, col: usize) -> Vec<bool> {
+        let mut neighbors = Vec::new();
+        for dr in [-1, 0, 1] {
+            for dc in [-1, 0, 1] {
+                if (dr, dc) == (0, 0) {
+                    continue;
+                }
+                let (nr, nc) = (row as isize + dr, col as isize + dc);
+                let (rows, cols) = (self.rows as isize, self.cols as isize);
+                match self.boundary {
+                    Boundary::Torus => neighbors.push(
+                        self.get_light(nr.rem_euclid(rows) as usize, nc.rem_euclid(cols) as usize),
+                    ),
+                    Boundary::Dead | Boundary::Unbounded => {
+                        if (0..rows).contains(&nr) && (0..cols).contains(&nc) {
+                            neighbors.push(self.get_light(nr as usize, nc as usize));
+                        }
+                    }
                 }
+            }
+        }
+        neighbors
+    }
+
+    fn grow(&mut self) {
+        let row_on = |row: usize| {
+            self.cells[row * self.words..(row + 1) * self.words]
+                .iter()
+                .any(|&word| word != 0)
+        };
+        let col_on = |col: usize| (0..self.rows).any(|row| self.get_light(row, col));
+        let top = row_on(0) as usize;
+        let bottom = row_on(self.rows - 1) as usize;
+        let left = col_on(0) as usize;
+        let right = col_on(self.cols - 1) as usize;
+        if top + bottom + left + right > 0 {
+            self.reframe(
+                -(top as isize),
+                -(left as isize),
+                self.rows + top + bottom,
+                self.cols + left + right,
+            );
+        }
+    }
+
+    fn trim(&mut self) {
+        let mut bounds: Option<(usize, usize, usize, usize)> = None;
+        let (cells, words) = (&self.cells, self.words);
+        let live_cells = (0..self.rows).flat_map(|row| {
+            (0..words).flat_map(move |w| {
+                let word = cells[row * words + w];
+                [
+                    (word != 0).then(|| (row, w * 64 + word.trailing_zeros() as usize)),
+                    (word != 0).then(|| (row, w * 64 + 63 - word.leading_zeros() as usize)),
+                ]
+                .into_iter()
+                .flatten()
             })
         });
-        let neighbors = delta_neighbors
-            .iter()
-            .map(|(dr, dc)| {
-                let nr = (row as isize + dr) as usize;
-                let nc = (col as isize + dc) as usize;
-                self.get_light(nr, nc)
+        for (row, col) in live_cells.chain(self.pinned.keys().copied()) {
+            bounds = Some(match bounds {
+                None => (row, row, col, col),
+                Some((top, bottom, left, right)) => {
+                    (top.min(row), bottom.max(row), left.min(col), right.max(col))
+                }
+            });
+        }
+        let (top, bottom, left, right) = bounds.unwrap_or((0, 0, 0, 0));
+        let (rows, cols) = (bottom - top + 1, right - left + 1);
+        if (rows, cols) != (self.rows, self.cols) {
+            self.reframe(top as isize, left as isize, rows, cols);
+        }
+    }
+
+    fn reframe(&mut self, first_row: isize, first_col: isize, rows: usize, cols: usize) {
+        let mut framed = Lights::new(rows, cols);
+        (0..self.rows).for_each(|row| {
+            (0..self.cols)
+                .filter(|&col| self.get_light(row, col))
+                .for_each(|col| {
+                    let (r, c) = (row as isize - first_row, col as isize - first_col);
+                    if (0..rows as isize).contains(&r) && (0..cols as isize).contains(&c) {
+                        framed.set_light(r as usize, c as usize, true)
+                    }
+                })
+        });
+        self.cells = framed.cells;
+        self.next = framed.next;
+        self.rows = rows;
+        self.cols = cols;
+        self.words = framed.words;
+        self.origin = (self.origin.0 - first_row, self.origin.1 - first_col);
+        self.pinned = self
+            .pinned
+            .drain()
+            .map(|((row, col), on)| {
+                let row = (row as isize - first_row) as usize;
+                let col = (col as isize - first_col) as usize;
+                ((row, col), on)
             })
-            .collect::<Vec<_>>();
-        neighbors
+            .collect();
+    }
+
+    fn row(&self, row: isize) -> Option<&[u64]> {
+        let row = match self.boundary {
+            Boundary::Torus => row.rem_euclid(self.rows as isize) as usize,
+            Boundary::Dead | Boundary::Unbounded => {
+                if row < 0 || row >= self.rows as isize {
+                    return None;
+                }
+                row as usize
+            }
+        };
+        Some(&self.cells[row * self.words..(row + 1) * self.words])
+    }
+
+    fn west(&self, row: &[u64], word: usize) -> u64 {
+        let carry = if word > 0 {
+            row[word - 1] >> 63
+        } else if self.boundary == Boundary::Torus {
+            let last = self.cols - 1;
+            row[last / 64] >> (last % 64) & 1
+        } else {
+            0
+        };
+        row[word] << 1 | carry
+    }
+
+    fn east(&self, row: &[u64], word: usize) -> u64 {
+        let mut shifted = row[word] >> 1;
+        if word + 1 < self.words {
+            shifted |= row[word + 1] << 63;
+        } else if self.boundary == Boundary::Torus {
+            shifted |= (row[0] & 1) << ((self.cols - 1) % 64);
+        }
+        shifted
     }
 
     fn step(&mut self) {
+        if self.boundary == Boundary::Unbounded {
+            self.grow();
+        }
+        let masks = |set: &[bool; 9]| {
+            (0..9)
+                .filter(|&n| set[n])
+                .fold(0u16, |mask, n| mask | 1 << n)
+        };
+        let (birth, survival) = (masks(&self.rule.birth), masks(&self.rule.survival));
+        let tail = match self.cols % 64 {
+            0 => u64::MAX,
+            bits => (1 << bits) - 1,
+        };
+        let mut next = std::mem::take(&mut self.next);
+        for r in 0..self.rows {
+            let rows = [
+                self.row(r as isize - 1),
+                self.row(r as isize),
+                self.row(r as isize + 1),
+            ];
+            for w in 0..self.words {
+                let mut sums = [0u64; 4];
+                let mut add = |bits: u64| {
+                    let mut carry = bits;
+                    for sum in sums.iter_mut() {
+                        let overflow = *sum & carry;
+                        *sum ^= carry;
+                        carry = overflow;
+                    }
+                };
+                for (i, row) in rows.iter().enumerate() {
+                    if let Some(row) = row {
+                        add(self.west(row, w));
+                        add(self.east(row, w));
+                        if i != 1 {
+                            add(row[w]);
+                        }
+                    }
+                }
+                let alive = self.cells[r * self.words + w];
+                let mut born = 0;
+                let mut survives = 0;
+                for count in 0..9 {
+                    let matches = (0..4).fold(u64::MAX, |matches, bit| {
+                        if count >> bit & 1 == 1 {
+                            matches & sums[bit]
+                        } else {
+                            matches & !sums[bit]
+                        }
+                    });
+                    if birth >> count & 1 == 1 {
+                        born |= matches;
+                    }
+                    if survival >> count & 1 == 1 {
+                        survives |= matches;
+                    }
+                }
+                let mut word = (!alive & born) | (alive & survives);
+                if w + 1 == self.words {
+                    word &= tail;
+                }
+                next[r * self.words + w] = word;
+            }
+        }
+        self.next = std::mem::replace(&mut self.cells, next);
+        self.apply_pinned();
+        if self.boundary == Boundary::Unbounded {
+            self.trim();
+        }
+    }
+
+    #[cfg(test)]
+    fn step_naive(&mut self) {
+        if self.boundary == Boundary::Unbounded {
+            self.grow();
+        }
         let next = (0..self.rows)
             .flat_map(|row| {
                 (0..self.cols)
                     .map(|col| {
                         let light = self.get_light(row, col);
                         let neighbors = self.get_neighbors(row, col);
-                        let count = neighbors.iter().filter(|&n| **n == true).count();
-                        match light {
-                            true => match count {
-                                2 | 3 => true,
-                                _ => false,
-                            },
-                            false => match count {
-                                3 => true,
-                                _ => false,
-                            },
-                        }
+                        let count = neighbors.iter().filter(|&n| *n).count();
+                        self.rule.next(light, count)
                     })
                     .collect::<Vec<_>>()
             })
             .collect::<Vec<_>>();
-        self.grid = next;
+        next.iter().enumerate().for_each(|(i, &on)| {
+            self.set_light(i / self.cols, i % self.cols, on);
+        });
+        self.apply_pinned();
+        if self.boundary == Boundary::Unbounded {
+            self.trim();
+        }
+    }
+
+    fn apply_pinned(&mut self) {
+        let pinned = std::mem::take(&mut self.pinned);
+        pinned
+            .iter()
+            .for_each(|(&(row, col), &on)| self.set_light(row, col, on));
+        self.pinned = pinned;
     }
 
     fn count_lights_on(&self) -> usize {
+        self.cells
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PatternFormat {
+    Grid,
+    Rle,
+    Plaintext,
+}
+
+impl From<&str> for PatternFormat {
+    fn from(value: &str) -> Self {
+        match value {
+            "grid" | "txt" => PatternFormat::Grid,
+            "rle" => PatternFormat::Rle,
+            "cells" => PatternFormat::Plaintext,
+            f => panic!("Unknown pattern format {f}"),
+        }
+    }
+}
+
+impl PatternFormat {
+    fn from_path(path: &str) -> Self {
+        path.rsplit_once('.')
+            .map_or(PatternFormat::Grid, |(_, extension)| {
+                PatternFormat::from(extension)
+            })
+    }
+}
+
+impl Lights {
+    fn parse(input: &str, format: PatternFormat) -> Self {
+        match format {
+            PatternFormat::Grid => Lights::from(input),
+            PatternFormat::Rle => Lights::from_rle(input),
+            PatternFormat::Plaintext => Lights::from_plaintext(input),
+        }
+    }
+
+    fn render(&self, format: PatternFormat) -> String {
+        match format {
+            PatternFormat::Grid => self.to_string(),
+            PatternFormat::Rle => self.to_rle(),
+            PatternFormat::Plaintext => self.to_plaintext(),
+        }
+    }
+
+    fn from_rle(input: &str) -> Self {
+        let mut lines = input
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        let header = lines.next().expect("Missing RLE header");
+        let (mut rows, mut cols, mut rule) = (0, 0, Rule::default());
+        header.split(',').for_each(|field| {
+            let (key, value) = field
+                .split_once('=')
+                .unwrap_or_else(|| panic!("Invalid RLE header {header}"));
+            match key.trim() {
+                "x" => cols = value.trim().parse().unwrap(),
+                "y" => rows = value.trim().parse().unwrap(),
+                "rule" => rule = Rule::from(value.trim()),
+                k => panic!("Unknown RLE header field {k}"),
+            }
+        });
+        let mut lights = Lights::new(rows, cols).with_rule(rule);
+        let (mut row, mut col, mut run) = (0, 0, 0);
+        for c in lines.flat_map(|line| line.chars()) {
+            let count = run.max(1);
+            match c {
+                '0'..='9' => {
+                    run = run * 10 + c.to_digit(10).unwrap() as usize;
+                    continue;
+                }
+                'b' | '.' => col += count,
+                '$' => {
+                    row += count;
+                    col = 0;
+                }
+                '!' => break,
+                c if c.is_ascii_alphabetic() => {
+                    (col..col + count).for_each(|col| lights.set_light(row, col, true));
+                    col += count;
+                }
+                c if c.is_whitespace() => {}
+                c => panic!("Unknown RLE tag {c}"),
+            }
+            run = 0;
+        }
+        lights
+    }
+
+    fn to_rle(&self) -> String {
+        let mut tokens = Vec::new();
+        let mut push = |count: usize, tag: char| match count {
+            0 => {}
+            1 => tokens.push(tag.to_string()),
+            n => tokens.push(format!("{n}{tag}")),
+        };
+        let mut last_row = 0;
+        for row in 0..self.rows {
+            let mut runs: Vec<(bool, usize)> = Vec::new();
+            (0..self.cols).for_each(|col| {
+                let on = self.get_light(row, col);
+                match runs.last_mut() {
+                    Some((last, count)) if *last == on => *count += 1,
+                    _ => runs.push((on, 1)),
+                }
+            });
+            if runs.last().is_some_and(|&(on, _)| !on) {
+                runs.pop();
+            }
+            if runs.is_empty() {
+                continue;
+            }
+            push(row - last_row, '$');
+            last_row = row;
+            runs.iter()
+                .for_each(|&(on, count)| push(count, if on { 'o' } else { 'b' }));
+        }
+        tokens.push("!".to_string());
+        let mut body = String::new();
+        let mut line_len = 0;
+        tokens.iter().for_each(|token| {
+            if line_len + token.len() > 70 {
+                body.push('\n');
+                line_len = 0;
+            }
+            body.push_str(token);
+            line_len += token.len();
+        });
+        format!(
+            "x = {}, y = {}, rule = {}\n{}",
+            self.cols, self.rows, self.rule, body
+        )
+    }
+
+    fn from_plaintext(input: &str) -> Self {
+        let rows = input
+            .lines()
+            .map(str::trim_end)
+            .filter(|line| !line.starts_with('!'))
+            .collect::<Vec<_>>();
+        let cols = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+        let mut lights = Lights::new(rows.len(), cols);
+        rows.iter().enumerate().for_each(|(row, line)| {
+            line.chars().enumerate().for_each(|(col, c)| match c {
+                '.' => {}
+                'O' | '*' => lights.set_light(row, col, true),
+                c => panic!("Unknown character {c}"),
+            })
+        });
+        lights
+    }
+
+    fn to_plaintext(&self) -> String {
         (0..self.rows)
             .map(|row| {
                 (0..self.cols)
-                    .map(|col| match self.get_light(row, col) {
-                        true => 1,
-                        false => 0,
-                    })
-                    .sum::<usize>()
+                    .map(|col| if self.get_light(row, col) { 'O' } else { '.' })
+                    .collect::<String>()
             })
-            .sum()
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn with_pattern(mut self, pattern: &Lights, row: usize, col: usize) -> Self {
+        assert!(
+            row + pattern.rows <= self.rows && col + pattern.cols <= self.cols,
+            "Pattern of size {}x{} does not fit at ({row}, {col})",
+            pattern.rows,
+            pattern.cols
+        );
+        (0..pattern.rows).for_each(|r| {
+            (0..pattern.cols)
+                .for_each(|c| self.set_light(row + r, col + c, pattern.get_light(r, c)))
+        });
+        self.apply_pinned();
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cycle {
+    start: usize,
+    period: usize,
+}
+
+impl Cycle {
+    fn is_still_life(&self) -> bool {
+        self.period == 1
     }
 }
 
+impl Lights {
+    fn same_state(&self, other: &Lights) -> bool {
+        (self.rows, self.cols, self.origin) == (other.rows, other.cols, other.origin)
+            && self.cells == other.cells
+    }
+
+    // Brent's cycle detection keeps only a few grids alive, however long the run.
+    fn run(&mut self, generations: usize) -> Option<Cycle> {
+        if generations == 0 {
+            return None;
+        }
+        let initial = self.clone();
+        let mut tortoise = self.clone();
+        let (mut power, mut period) = (1, 1);
+        self.step();
+        let mut generation = 1;
+        while !self.same_state(&tortoise) {
+            if generation == generations {
+                return None;
+            }
+            if power == period {
+                tortoise = self.clone();
+                power *= 2;
+                period = 0;
+            }
+            self.step();
+            generation += 1;
+            period += 1;
+        }
+        let mut tortoise = initial.clone();
+        let mut hare = initial;
+        (0..period).for_each(|_| hare.step());
+        let mut start = 0;
+        while !tortoise.same_state(&hare) {
+            tortoise.step();
+            hare.step();
+            start += 1;
+        }
+        (0..(generations - start) % period).for_each(|_| tortoise.step());
+        *self = tortoise;
+        Some(Cycle { start, period })
+    }
+}
+
+impl Lights {
+    fn to_pgm(&self, scale: usize) -> Vec<u8> {
+        let (width, height) = (self.cols * scale, self.rows * scale);
+        let mut image = format!("P5\n{width} {height}\n255\n").into_bytes();
+        (0..height).for_each(|y| {
+            image.extend((0..width).map(|x| {
+                if self.get_light(y / scale, x / scale) {
+                    255
+                } else {
+                    0
+                }
+            }))
+        });
+        image
+    }
+}
+
+fn write_frames(
+    lights: &mut Lights,
+    generations: usize,
+    dir: &Path,
+    scale: usize,
+) -> io::Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir)?;
+    let digits = generations.to_string().len();
+    let mut frames = Vec::new();
+    for generation in 0..=generations {
+        if generation > 0 {
+            lights.step();
+        }
+        let path = dir.join(format!("frame_{generation:0digits$}.pgm"));
+        fs::write(&path, lights.to_pgm(scale))?;
+        frames.push(path);
+    }
+    Ok(frames)
+}
+
+fn animate(
+    lights: &mut Lights,
+    generations: usize,
+    delay: Duration,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    for generation in 0..=generations {
+        if generation > 0 {
+            lights.step();
+        }
+        write!(
+            out,
+            "\x1b[H\x1b[2J{lights}\nGeneration {generation}, {} lights on\n",
+            lights.count_lights_on()
+        )?;
+        out.flush()?;
+        thread::sleep(delay);
+    }
+    Ok(())
+}
+
 fn main() {
-    let input = read_input("day18.txt");
-    let mut lights = Lights::from(input.as_str());
-    (0..100).for_each(|_| lights.step());
-    println!("Part 1 = {}", lights.count_lights_on());
-    let input = read_input("day18.txt");
-    let mut lights = Lights::from(input.as_str()).with_locked_corners();
-    (0..100).for_each(|_| lights.step());
-    println!("Part 2 = {}", lights.count_lights_on());
+    let args = std::env::args().collect::<Vec<_>>();
+    let flag = |name: &str| {
+        args.iter()
+            .position(|arg| arg == name)
+            .and_then(|i| args.get(i + 1))
+            .map(|value| value.as_str())
+    };
+    let boundary = flag("--boundary").map(Boundary::from).unwrap_or_default();
+    let load = || match flag("--pattern") {
+        Some(path) => {
+            let input = fs::read_to_string(path).unwrap();
+            let pattern = Lights::parse(&input, PatternFormat::from_path(path));
+            let (rows, cols) = (pattern.rows.max(100), pattern.cols.max(100));
+            Lights::new(rows, cols)
+                .with_rule(pattern.rule.clone())
+                .with_pattern(
+                    &pattern,
+                    (rows - pattern.rows) / 2,
+                    (cols - pattern.cols) / 2,
+                )
+        }
+        None => Lights::from(read_input("day18.txt").as_str()),
+    };
+    let with_rule = |lights: Lights| match flag("--rule") {
+        Some(rule) => lights.with_rule(Rule::from(rule)),
+        None => lights,
+    };
+    let generations = flag("--generations").map_or(100, |n| n.parse().unwrap());
+    let report = |lights: &mut Lights| {
+        match lights.run(generations) {
+            Some(cycle) if cycle.is_still_life() => {
+                println!("Still life from generation {}", cycle.start)
+            }
+            Some(cycle) => println!(
+                "Generation {} repeats with period {}",
+                cycle.start, cycle.period
+            ),
+            None => {}
+        }
+        if lights.boundary == Boundary::Unbounded {
+            let (row, col) = lights.origin();
+            println!(
+                "Live cells fit in {}x{} with the input's top-left corner at ({row}, {col})",
+                lights.rows, lights.cols
+            );
+        }
+        lights.count_lights_on()
+    };
+    if let Some(delay) = flag("--animate") {
+        let delay = Duration::from_millis(delay.parse().unwrap());
+        let mut lights = with_rule(load()).with_boundary(boundary);
+        animate(&mut lights, generations, delay, &mut io::stdout()).unwrap();
+    }
+    if let Some(dir) = flag("--frames") {
+        let mut lights = with_rule(load()).with_boundary(boundary);
+        let frames = write_frames(&mut lights, generations, Path::new(dir), 4).unwrap();
+        println!("Wrote {} frames to {dir}", frames.len());
+    }
+    let mut lights = with_rule(load()).with_boundary(boundary);
+    println!("Part 1 = {}", report(&mut lights));
+    if let Some(format) = flag("--export") {
+        println!("{}", lights.render(PatternFormat::from(format)));
+    }
+    let mut lights = with_rule(load())
+        .with_boundary(boundary)
+        .with_locked_corners();
+    println!("Part 2 = {}", report(&mut lights));
 }
 
 #[cfg(test)]
@@ -274,4 +937,329 @@ mod day18_tests {
         assert_eq!(lights.to_string(), after_5_step);
         assert_eq!(lights.count_lights_on(), 17);
     }
+
+    #[test]
+    fn rulestrings() {
+        assert_eq!(Rule::default().to_string(), "B3/S23");
+        assert_eq!(Rule::from("b36/s23").to_string(), "B36/S23");
+        let seeds = Rule::from("B2/S");
+        assert!(seeds.next(false, 2));
+        assert!(!seeds.next(true, 2));
+        assert!(!seeds.next(true, 3));
+    }
+
+    #[test]
+    fn seeds() {
+        let mut lights = Lights::from(
+            r#"....
+.##.
+....
+...."#,
+        )
+        .with_rule(Rule::from("B2/S"));
+        lights.step();
+        assert_eq!(
+            lights.to_string(),
+            r#".##.
+....
+.##.
+...."#
+        );
+    }
+
+    #[test]
+    fn pinned_cells() {
+        let mut lights = Lights::from(
+            r#"...
+###
+..."#,
+        )
+        .with_pinned(&[(1, 0)], false)
+        .with_pinned(&[(0, 0)], true);
+        assert_eq!(
+            lights.to_string(),
+            r#"#..
+.##
+..."#
+        );
+        lights.step();
+        assert_eq!(
+            lights.to_string(),
+            r#"##.
+.#.
+..."#
+        );
+    }
+
+    const GLIDER: &str = r#".#...
+..#..
+###..
+.....
+....."#;
+
+    #[test]
+    fn torus() {
+        let mut lights = Lights::from(GLIDER).with_boundary(Boundary::Torus);
+        (0..20).for_each(|_| lights.step());
+        assert_eq!(lights.to_string(), GLIDER);
+        let mut lights = Lights::from(GLIDER);
+        (0..20).for_each(|_| lights.step());
+        assert_eq!(lights.count_lights_on(), 4);
+        assert_ne!(lights.to_string(), GLIDER);
+    }
+
+    #[test]
+    fn unbounded() {
+        let mut lights = Lights::from(GLIDER).with_boundary(Boundary::Unbounded);
+        (0..40).for_each(|_| lights.step());
+        assert_eq!(lights.count_lights_on(), 5);
+        assert_eq!(lights.origin(), (-10, -10));
+        assert_eq!(lights.to_string(), ".#.\n..#\n###");
+    }
+
+    #[test]
+    fn escaping_glider_keeps_grid_small() {
+        let mut lights = Lights::from(GLIDER).with_boundary(Boundary::Unbounded);
+        for _ in 0..1000 {
+            lights.step();
+            assert!(lights.rows <= 4 && lights.cols <= 4);
+        }
+        assert_eq!(lights.origin(), (-250, -250));
+        assert_eq!(lights.count_lights_on(), 5);
+    }
+
+    fn random_lights(rows: usize, cols: usize, seed: u64) -> Lights {
+        let mut state = seed;
+        let mut lights = Lights::new(rows, cols);
+        (0..rows).for_each(|row| {
+            (0..cols).for_each(|col| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                lights.set_light(row, col, state.is_multiple_of(3));
+            })
+        });
+        lights
+    }
+
+    #[test]
+    fn bit_packed_matches_naive() {
+        for (rows, cols) in [(1, 1), (3, 5), (17, 64), (20, 70), (9, 129)] {
+            for boundary in [Boundary::Dead, Boundary::Torus, Boundary::Unbounded] {
+                for rule in ["B3/S23", "B36/S23", "B2/S", "B0123478/S34678"] {
+                    let mut fast = random_lights(rows, cols, 0x9e3779b97f4a7c15)
+                        .with_boundary(boundary)
+                        .with_rule(Rule::from(rule))
+                        .with_pinned(&[(0, 0)], true);
+                    let mut naive = random_lights(rows, cols, 0x9e3779b97f4a7c15)
+                        .with_boundary(boundary)
+                        .with_rule(Rule::from(rule))
+                        .with_pinned(&[(0, 0)], true);
+                    for _ in 0..10 {
+                        fast.step();
+                        naive.step_naive();
+                        assert_eq!(fast.to_string(), naive.to_string());
+                        assert_eq!(fast.count_lights_on(), naive.count_lights_on());
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn benchmark_step() {
+        let generations = 100;
+        let mut fast = random_lights(512, 512, 42).with_boundary(Boundary::Torus);
+        let mut naive = random_lights(512, 512, 42).with_boundary(Boundary::Torus);
+        let start = std::time::Instant::now();
+        (0..generations).for_each(|_| naive.step_naive());
+        let naive_time = start.elapsed();
+        let start = std::time::Instant::now();
+        (0..generations).for_each(|_| fast.step());
+        let fast_time = start.elapsed();
+        assert_eq!(fast.to_string(), naive.to_string());
+        println!(
+            "512x512 x {generations} generations: naive {:?}, bit-packed {:?} ({:.1}x)",
+            naive_time,
+            fast_time,
+            naive_time.as_secs_f64() / fast_time.as_secs_f64()
+        );
+    }
+
+    const GOSPER_GUN: &str = r#"#N Gosper glider gun
+#C The first known gun.
+x = 36, y = 9, rule = B3/S23
+24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o$2o8bo3bob2o4b
+obo$10bo5bo7bo$11bo3bo$12b2o!"#;
+
+    #[test]
+    fn rle() {
+        let gun = Lights::from_rle(GOSPER_GUN);
+        assert_eq!((gun.rows, gun.cols), (9, 36));
+        assert_eq!(gun.count_lights_on(), 36);
+        assert_eq!(
+            gun.to_rle(),
+            GOSPER_GUN.lines().skip(2).collect::<Vec<_>>().join("\n")
+        );
+        let glider = Lights::from(GLIDER).with_rule(Rule::from("B36/S23"));
+        assert_eq!(glider.to_rle(), "x = 5, y = 5, rule = B36/S23\nbo$2bo$3o!");
+        assert_eq!(Lights::from_rle(&glider.to_rle()).to_string(), GLIDER);
+        assert_eq!(
+            Lights::from_rle("x = 3, y = 4\n3$o!").to_string(),
+            "...\n...\n...\n#.."
+        );
+    }
+
+    #[test]
+    fn plaintext() {
+        let glider = Lights::from_plaintext("!Name: Glider\n!\n.O\n..O\nOOO");
+        assert_eq!(glider.to_plaintext(), ".O.\n..O\nOOO");
+        assert_eq!(
+            Lights::parse(&glider.render(PatternFormat::Rle), PatternFormat::Rle).to_string(),
+            ".#.\n..#\n###"
+        );
+        assert_eq!(PatternFormat::from_path("gun.rle"), PatternFormat::Rle);
+        assert_eq!(
+            PatternFormat::from_path("glider.cells"),
+            PatternFormat::Plaintext
+        );
+    }
+
+    #[test]
+    fn place_pattern() {
+        let glider = Lights::from_plaintext(".O\n..O\nOOO");
+        let mut lights = Lights::new(6, 6)
+            .with_boundary(Boundary::Torus)
+            .with_pattern(&glider, 2, 3);
+        assert_eq!(
+            lights.to_string(),
+            "......\n......\n....#.\n.....#\n...###\n......"
+        );
+        (0..4).for_each(|_| lights.step());
+        assert_eq!(
+            lights.to_string(),
+            "......\n......\n......\n.....#\n#.....\n#...##"
+        );
+        let mut gun = Lights::new(40, 60).with_pattern(&Lights::from_rle(GOSPER_GUN), 1, 1);
+        (0..30).for_each(|_| gun.step());
+        assert_eq!(gun.count_lights_on(), 36 + 5);
+    }
+
+    #[test]
+    fn still_lifes_and_oscillators() {
+        let mut block = Lights::from("....\n.##.\n.##.\n....");
+        let cycle = block.run(10).unwrap();
+        assert_eq!(
+            cycle,
+            Cycle {
+                start: 0,
+                period: 1
+            }
+        );
+        assert!(cycle.is_still_life());
+
+        let mut blinker = Lights::from(".....\n.....\n.###.\n.....\n.....");
+        assert_eq!(
+            blinker.run(1_000_000_000_001),
+            Some(Cycle {
+                start: 0,
+                period: 2
+            })
+        );
+        assert_eq!(blinker.to_string(), ".....\n..#..\n..#..\n..#..\n.....");
+
+        let mut lights = Lights::from(GLIDER);
+        let cycle = lights.run(100).unwrap();
+        assert!(cycle.is_still_life());
+        assert_eq!(cycle.start, 11);
+        assert_eq!(lights.to_string(), ".....\n.....\n.....\n...##\n...##");
+    }
+
+    #[test]
+    fn jump_ahead() {
+        let mut lights = Lights::from(GLIDER).with_boundary(Boundary::Torus);
+        assert_eq!(
+            lights.run(1000),
+            Some(Cycle {
+                start: 0,
+                period: 20
+            })
+        );
+        assert_eq!(lights.to_string(), GLIDER);
+        let cycle = random_lights(12, 9, 7)
+            .with_boundary(Boundary::Torus)
+            .run(10_000)
+            .unwrap();
+        for generations in [7, 33, cycle.start + 5 * cycle.period + 3, 1_000_000_007] {
+            let mut jumped = random_lights(12, 9, 7).with_boundary(Boundary::Torus);
+            let mut stepped = random_lights(12, 9, 7).with_boundary(Boundary::Torus);
+            jumped.run(generations);
+            let equivalent = if generations < cycle.start {
+                generations
+            } else {
+                cycle.start + (generations - cycle.start) % cycle.period
+            };
+            (0..equivalent).for_each(|_| stepped.step());
+            assert_eq!(jumped.to_string(), stepped.to_string());
+            assert_eq!(jumped.count_lights_on(), stepped.count_lights_on());
+        }
+    }
+
+    #[test]
+    fn long_run_without_cycle() {
+        let mut lights = Lights::from(GLIDER).with_boundary(Boundary::Unbounded);
+        assert_eq!(lights.run(400), None);
+        assert_eq!(lights.count_lights_on(), 5);
+        assert_eq!((lights.rows, lights.cols), (3, 3));
+        assert_eq!(lights.origin(), (-100, -100));
+    }
+
+    #[test]
+    fn no_cycle() {
+        let mut lights = Lights::from(GLIDER).with_boundary(Boundary::Unbounded);
+        assert_eq!(lights.run(50), None);
+        assert_eq!(lights.count_lights_on(), 5);
+    }
+
+    #[test]
+    fn pgm() {
+        let lights = Lights::from("#.\n.#");
+        assert_eq!(lights.to_pgm(1), b"P5\n2 2\n255\n\xff\x00\x00\xff");
+        let image = lights.to_pgm(2);
+        assert!(image.starts_with(b"P5\n4 4\n255\n"));
+        assert_eq!(
+            &image[11..],
+            [255, 255, 0, 0, 255, 255, 0, 0, 0, 0, 255, 255, 0, 0, 255, 255]
+        );
+    }
+
+    #[test]
+    fn frames() {
+        let dir = std::env::temp_dir().join(format!("day18_frames_{}", std::process::id()));
+        let mut lights = Lights::from(GLIDER);
+        let frames = write_frames(&mut lights, 10, &dir, 3).unwrap();
+        assert_eq!(frames.len(), 11);
+        assert!(frames[0].ends_with("frame_00.pgm"));
+        assert!(frames[10].ends_with("frame_10.pgm"));
+        let first = fs::read(&frames[0]).unwrap();
+        assert_eq!(first, Lights::from(GLIDER).to_pgm(3));
+        assert_eq!(fs::read(&frames[10]).unwrap(), lights.to_pgm(3));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn terminal_animation() {
+        let mut lights = Lights::from(".....\n.....\n.###.\n.....\n.....");
+        let mut out = Vec::new();
+        animate(&mut lights, 2, Duration::ZERO, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let frames = out.split("\x1b[H\x1b[2J").skip(1).collect::<Vec<_>>();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(
+            frames[1],
+            ".....\n..#..\n..#..\n..#..\n.....\nGeneration 1, 3 lights on\n"
+        );
+        assert_eq!(frames[0], frames[2].replace("Generation 2", "Generation 0"));
+    }
 }
@@ -1,9 +1,16 @@
+mod number_theory;
+
+#[cfg(test)]
 use std::collections::HashMap;
+use std::thread;
 
+use number_theory::{divisors, sigma_with_limit};
+
+#[cfg(test)]
 fn divisors_of(n: usize) -> Vec<usize> {
     let mut divisors = Vec::new();
     (1..=((n as f64).sqrt() as usize))
-        .filter(|i| n % i == 0)
+        .filter(|i| n.is_multiple_of(*i))
         .for_each(|i| {
             divisors.push(i);
             if i != n / i {
@@ -13,6 +20,7 @@ fn divisors_of(n: usize) -> Vec<usize> {
     divisors
 }
 
+#[cfg(test)]
 fn presents_at_house(
     n: usize,
     memo: &mut HashMap<usize, u32>,
@@ -22,25 +30,104 @@ fn presents_at_house(
     if let Some(&presents) = memo.get(&n) {
         return presents;
     }
-    let presents = divisors_of(n)
-        .into_iter()
-        .filter(|&d| limit.map_or(true, |limit| n / d <= limit))
-        .map(|d| d as u32 * multiplier)
-        .sum();
+    let presents = sigma_with_limit(n, limit) as u32 * multiplier;
     memo.insert(n, presents);
     presents
 }
 
-fn find_house_with(presents: u32, multiplier: u32, limit: Option<usize>) -> usize {
-    let mut n = 1;
-    let mut memo = HashMap::new();
-    while presents_at_house(n, &mut memo, multiplier, limit) < presents {
-        n += 1;
+const CHUNK_SIZE: usize = 1 << 18;
+
+fn first_house_in_chunk(
+    start: usize,
+    end: usize,
+    presents: u32,
+    multiplier: u32,
+    limit: Option<usize>,
+) -> Option<usize> {
+    let mut houses = vec![0u64; end - start];
+    // Elves that already delivered their last present before `start` are skipped.
+    let first_elf = limit.map_or(1, |limit| start.div_ceil(limit).max(1));
+    for elf in first_elf..end {
+        let last_house = limit.map_or(end - 1, |limit| (elf * limit).min(end - 1));
+        let mut house = start.div_ceil(elf).max(1) * elf;
+        while house <= last_house {
+            houses[house - start] += elf as u64 * multiplier as u64;
+            house += elf;
+        }
     }
-    n
+    houses
+        .iter()
+        .position(|&p| p >= presents as u64)
+        .map(|idx| start + idx)
+}
+
+fn find_house_in_chunks(
+    presents: u32,
+    multiplier: u32,
+    limit: Option<usize>,
+    chunk_size: usize,
+) -> usize {
+    // Elf n always visits house n, so that house gets at least n * multiplier.
+    let upper = (presents as usize).div_ceil(multiplier as usize).max(1);
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    let mut start = 1;
+    while start <= upper {
+        let found = thread::scope(|scope| {
+            let handles = (0..workers)
+                .map(|worker| {
+                    let chunk_start = start + worker * chunk_size;
+                    let chunk_end = (chunk_start + chunk_size).min(upper + 1);
+                    scope.spawn(move || {
+                        if chunk_start < chunk_end {
+                            first_house_in_chunk(
+                                chunk_start,
+                                chunk_end,
+                                presents,
+                                multiplier,
+                                limit,
+                            )
+                        } else {
+                            None
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .find_map(|handle| handle.join().unwrap())
+        });
+        if let Some(house) = found {
+            return house;
+        }
+        start += workers * chunk_size;
+    }
+    unreachable!("House {upper} always receives enough presents")
+}
+
+fn find_house_with(presents: u32, multiplier: u32, limit: Option<usize>) -> usize {
+    find_house_in_chunks(presents, multiplier, limit, CHUNK_SIZE)
 }
 
 fn main() {
+    let args = std::env::args().collect::<Vec<_>>();
+    if let Some(house) = args
+        .iter()
+        .position(|arg| arg == "--house")
+        .and_then(|i| args.get(i + 1))
+    {
+        let house = house.parse().unwrap();
+        println!(
+            "House {house} gets {} presents in part 1 and {} in part 2",
+            sigma_with_limit(house, None) * 10,
+            sigma_with_limit(house, Some(50)) * 11
+        );
+        let elves = divisors(house);
+        let still_delivering = elves.iter().filter(|&&elf| house / elf <= 50).count();
+        println!(
+            "{} elves visit it, {still_delivering} of them in part 2",
+            elves.len()
+        );
+    }
     println!("Part 1 = {}", find_house_with(34000000, 10, None));
     println!("Part 2 = {}", find_house_with(34000000, 11, Some(50)));
 }
@@ -61,4 +148,19 @@ mod day20_tests {
             presents
         );
     }
+
+    #[parameterized(
+        presents = {10, 70, 150, 1000, 5000, 29000, 100000, 100000},
+        multiplier = {10, 10, 10, 11, 10, 11, 11, 10},
+        limit = {None, None, None, Some(50), None, Some(50), Some(2), Some(10)}
+    )]
+    fn sieve_matches_brute_force(presents: u32, multiplier: u32, limit: Option<usize>) {
+        let mut memo = HashMap::new();
+        let mut house = 1;
+        while presents_at_house(house, &mut memo, multiplier, limit) < presents {
+            house += 1;
+        }
+        assert_eq!(find_house_with(presents, multiplier, limit), house);
+        assert_eq!(find_house_in_chunks(presents, multiplier, limit, 7), house);
+    }
 }
@@ -0,0 +1,119 @@
+pub fn factorise(mut n: usize) -> Vec<(usize, u32)> {
+    let mut factors = Vec::new();
+    let mut p = 2;
+    while p * p <= n {
+        if n.is_multiple_of(p) {
+            let mut exponent = 0;
+            while n.is_multiple_of(p) {
+                n /= p;
+                exponent += 1;
+            }
+            factors.push((p, exponent));
+        }
+        p += if p == 2 { 1 } else { 2 };
+    }
+    if n > 1 {
+        factors.push((n, 1));
+    }
+    factors
+}
+
+// sigma is multiplicative and sigma(p^k) = 1 + p + ... + p^k.
+pub fn sigma(n: usize) -> usize {
+    factorise(n)
+        .into_iter()
+        .map(|(p, k)| (0..=k).map(|e| p.pow(e)).sum::<usize>())
+        .product()
+}
+
+pub fn divisors(n: usize) -> Vec<usize> {
+    let mut divisors = factorise(n).into_iter().fold(vec![1], |divisors, (p, k)| {
+        divisors
+            .iter()
+            .flat_map(|d| (0..=k).map(move |e| d * p.pow(e)))
+            .collect()
+    });
+    divisors.sort();
+    divisors
+}
+
+// Sum of the divisors d of n with n / d <= limit, i.e. of the elves that still
+// deliver to house n when each elf stops after `limit` houses.
+pub fn sigma_with_limit(n: usize, limit: Option<usize>) -> usize {
+    match limit {
+        None => sigma(n),
+        Some(limit) => (1..=limit.min(n))
+            .filter(|q| n.is_multiple_of(*q))
+            .map(|q| n / q)
+            .sum(),
+    }
+}
+
+#[cfg(test)]
+mod number_theory_tests {
+    use super::*;
+    use crate::divisors_of;
+
+    #[test]
+    fn factorisation() {
+        assert_eq!(factorise(1), vec![]);
+        assert_eq!(factorise(360), vec![(2, 3), (3, 2), (5, 1)]);
+        assert_eq!(factorise(999_983), vec![(999_983, 1)]);
+        assert_eq!(divisors(12), vec![1, 2, 3, 4, 6, 12]);
+    }
+
+    #[test]
+    fn large_prime_factor() {
+        assert_eq!(sigma(4_294_967_311), 4_294_967_312);
+        assert_eq!(sigma(2 * 4_294_967_311), 3 * 4_294_967_312);
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        for n in 1..=10_000 {
+            let mut brute_force = divisors_of(n);
+            assert_eq!(sigma(n), brute_force.iter().sum::<usize>(), "sigma({n})");
+            brute_force.sort();
+            assert_eq!(divisors(n), brute_force, "divisors({n})");
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn matches_brute_force_up_to_a_million() {
+        for n in 1..=1_000_000 {
+            let mut brute_force = divisors_of(n);
+            assert_eq!(sigma(n), brute_force.iter().sum::<usize>(), "sigma({n})");
+            assert_eq!(
+                sigma_with_limit(n, Some(50)),
+                brute_force.iter().filter(|&&d| n / d <= 50).sum::<usize>(),
+                "sigma_with_limit({n}, 50)"
+            );
+            brute_force.sort();
+            assert_eq!(divisors(n), brute_force, "divisors({n})");
+        }
+    }
+
+    #[test]
+    fn matches_sieve() {
+        const N: usize = 1_000_000;
+        let mut sums = vec![0; N + 1];
+        let mut limited_sums = vec![0; N + 1];
+        for d in 1..=N {
+            for (q, n) in (d..=N).step_by(d).enumerate() {
+                sums[n] += d;
+                if q < 50 {
+                    limited_sums[n] += d;
+                }
+            }
+        }
+        for n in 1..=N {
+            assert_eq!(sigma(n), sums[n], "sigma({n})");
+            assert_eq!(
+                sigma_with_limit(n, Some(50)),
+                limited_sums[n],
+                "sigma_with_limit({n}, 50)"
+            );
+        }
+    }
+}
@@ -1,10 +1,9 @@
-use core::panic;
+use std::{fs, ops::RangeInclusive};
 
 use common::read_input;
 
 #[derive(Debug, Clone)]
 struct Item {
-    #[allow(dead_code)]
     name: String,
     price: i32,
     damage: i32,
@@ -62,6 +61,14 @@ impl Equip {
         }
     }
 
+    fn get_name(&self) -> &str {
+        match self {
+            Equip::Weapon(w) => &w.name,
+            Equip::Armor(a) => &a.name,
+            Equip::Ring(r) => &r.name,
+        }
+    }
+
     fn get_price(&self) -> i32 {
         match self {
             Equip::Weapon(w) => w.price,
@@ -74,76 +81,36 @@ impl Equip {
 #[derive(Debug)]
 struct Hero {
     hp: i32,
-    weapon: Equip,
-    armor: Option<Equip>,
-    left_ring: Option<Equip>,
-    right_ring: Option<Equip>,
+    equips: Vec<Equip>,
 }
 
 impl Hero {
-    fn new(hp: i32, weapon: Equip) -> Self {
+    fn new(hp: i32) -> Self {
         Self {
             hp,
-            weapon,
-            armor: None,
-            left_ring: None,
-            right_ring: None,
+            equips: Vec::new(),
         }
     }
 
-    fn with_armor(mut self, armor: Equip) -> Self {
-        self.armor = Some(armor);
-        self
-    }
-
-    fn with_left_ring(mut self, ring: Equip) -> Self {
-        self.left_ring = Some(ring);
-        self
-    }
-
-    fn with_right_ring(mut self, ring: Equip) -> Self {
-        self.right_ring = Some(ring);
+    fn with_equip(mut self, equip: Equip) -> Self {
+        self.equips.push(equip);
         self
     }
 
     fn get_damage(&self) -> i32 {
-        let mut damage = self.weapon.get_damage();
-        damage += if let Some(ring) = &self.left_ring {
-            ring.get_damage()
-        } else {
-            0
-        };
-        damage += if let Some(ring) = &self.right_ring {
-            ring.get_damage()
-        } else {
-            0
-        };
-        damage
+        self.equips.iter().map(|e| e.get_damage()).sum()
     }
 
     fn get_armor(&self) -> i32 {
-        let mut damage = if let Some(armor) = &self.armor {
-            armor.get_armor()
-        } else {
-            0
-        };
-        damage += if let Some(ring) = &self.left_ring {
-            ring.get_armor()
-        } else {
-            0
-        };
-        damage += if let Some(ring) = &self.right_ring {
-            ring.get_armor()
-        } else {
-            0
-        };
-        damage
+        self.equips.iter().map(|e| e.get_armor()).sum()
     }
 
+    #[cfg(test)]
     fn hit(&mut self, boss: &mut Boss) {
         boss.hp -= 1.max(self.get_damage() - boss.armor)
     }
 
+    #[cfg(test)]
     fn fight(&mut self, boss: &mut Boss) -> bool {
         self.hit(boss);
         if boss.hp <= 0 {
@@ -156,10 +123,34 @@ impl Hero {
         self.fight(boss)
     }
 
+    fn fight_outcome(&self, boss: &Boss) -> FightReport {
+        let hero_hit = 1.max(self.get_damage() - boss.armor);
+        let boss_hit = 1.max(boss.damage - self.get_armor());
+        let hero_rounds = (boss.hp + hero_hit - 1) / hero_hit;
+        let boss_rounds = (self.hp + boss_hit - 1) / boss_hit;
+        if hero_rounds <= boss_rounds {
+            FightReport {
+                winner: Winner::Hero,
+                rounds: hero_rounds,
+                hero_hp: self.hp - (hero_rounds - 1) * boss_hit,
+                boss_hp: boss.hp - hero_rounds * hero_hit,
+            }
+        } else {
+            FightReport {
+                winner: Winner::Boss,
+                rounds: boss_rounds,
+                hero_hp: self.hp - boss_rounds * boss_hit,
+                boss_hp: boss.hp - boss_rounds * hero_hit,
+            }
+        }
+    }
+
     fn forecast_price(
         price_kind: PriceKind,
         outcome: Outcome,
         equips: &mut Vec<Vec<Equip>>,
+        boss: &Boss,
+        config: &Config,
     ) -> i32 {
         let equips = if let PriceKind::Max = price_kind {
             equips.reverse();
@@ -171,51 +162,15 @@ impl Hero {
         equips
             .iter()
             .find(|c| {
-                let (w, a, r1, r2) = c.iter().fold(
-                    (None, None, None, None),
-                    |(mut w, mut a, mut r1, mut r2), e| {
-                        match e {
-                            Equip::Weapon(_) => w = Some(e.clone()),
-                            Equip::Armor(_) => a = Some(e.clone()),
-                            Equip::Ring(_) => {
-                                if r1.is_none() {
-                                    r1 = Some(e.clone())
-                                } else if r2.is_none() {
-                                    r2 = Some(e.clone())
-                                } else {
-                                    panic!("Too many rings in stock")
-                                }
-                            }
-                        };
-                        (w, a, r1, r2)
-                    },
-                );
-                let input = read_input("day21.txt");
-                let mut boss = Boss::from(input);
-                let mut hero = if let Some(w) = w {
-                    Hero::new(100, w)
-                } else {
-                    panic!("No weapon for the hero!")
-                };
-                hero = if let Some(a) = a {
-                    hero.with_armor(a)
-                } else {
-                    hero
-                };
-                hero = if let Some(r1) = r1 {
-                    hero.with_left_ring(r1)
-                } else {
-                    hero
-                };
-                hero = if let Some(r2) = r2 {
-                    hero.with_right_ring(r2)
-                } else {
-                    hero
-                };
+                let hero = c
+                    .iter()
+                    .cloned()
+                    .fold(Hero::new(config.hero_hp), Hero::with_equip);
+                let winner = hero.fight_outcome(boss).winner;
                 if let Outcome::Win = outcome {
-                    hero.fight(&mut boss)
+                    winner == Winner::Hero
                 } else {
-                    !hero.fight(&mut boss)
+                    winner == Winner::Boss
                 }
             })
             .map(|c| c.iter().map(|e| e.get_price()).sum::<i32>())
@@ -223,6 +178,143 @@ impl Hero {
     }
 }
 
+#[derive(Debug, Clone)]
+struct SlotRules {
+    weapons: RangeInclusive<usize>,
+    armor: RangeInclusive<usize>,
+    rings: RangeInclusive<usize>,
+}
+
+impl Default for SlotRules {
+    fn default() -> Self {
+        Self {
+            weapons: 1..=1,
+            armor: 0..=1,
+            rings: 0..=2,
+        }
+    }
+}
+
+impl SlotRules {
+    fn max_equips(&self) -> usize {
+        self.weapons.end() + self.armor.end() + self.rings.end()
+    }
+
+    fn allows(&self, equips: &[Equip]) -> bool {
+        let (w, a, r) = equips.iter().fold((0, 0, 0), |(mut w, mut a, mut r), e| {
+            match e {
+                Equip::Weapon(_) => w += 1,
+                Equip::Armor(_) => a += 1,
+                Equip::Ring(_) => r += 1,
+            };
+            (w, a, r)
+        });
+        self.weapons.contains(&w) && self.armor.contains(&a) && self.rings.contains(&r)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Config {
+    hero_hp: i32,
+    slots: SlotRules,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            hero_hp: 100,
+            slots: SlotRules::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Winner {
+    Hero,
+    Boss,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FightReport {
+    winner: Winner,
+    rounds: i32,
+    hero_hp: i32,
+    boss_hp: i32,
+}
+
+#[derive(Debug, Clone)]
+struct Loadout {
+    equips: Vec<Equip>,
+    price: i32,
+    damage: i32,
+    armor: i32,
+    report: FightReport,
+}
+
+impl Loadout {
+    fn new(equips: &[Equip], boss: &Boss, config: &Config) -> Self {
+        let hero = equips
+            .iter()
+            .cloned()
+            .fold(Hero::new(config.hero_hp), Hero::with_equip);
+        Self {
+            equips: equips.to_vec(),
+            price: equips.iter().map(|e| e.get_price()).sum(),
+            damage: hero.get_damage(),
+            armor: hero.get_armor(),
+            report: hero.fight_outcome(boss),
+        }
+    }
+
+    fn dominates(&self, other: &Loadout) -> bool {
+        self.price <= other.price
+            && self.damage >= other.damage
+            && self.armor >= other.armor
+            && (self.price < other.price || self.damage > other.damage || self.armor > other.armor)
+    }
+}
+
+fn analyse_loadouts(combinations: &[Vec<Equip>], boss: &Boss, config: &Config) -> Vec<Loadout> {
+    let mut loadouts = combinations
+        .iter()
+        .map(|c| Loadout::new(c, boss, config))
+        .collect::<Vec<_>>();
+    loadouts.sort_by_key(|l| l.price);
+    loadouts
+}
+
+fn pareto_frontier(loadouts: &[Loadout]) -> Vec<&Loadout> {
+    loadouts
+        .iter()
+        .filter(|l| !loadouts.iter().any(|other| other.dominates(l)))
+        .collect()
+}
+
+fn loadouts_to_csv(loadouts: &[Loadout]) -> String {
+    let frontier = pareto_frontier(loadouts);
+    let mut csv = "items,price,damage,armor,winner,rounds,hero_hp,boss_hp,pareto\n".to_string();
+    loadouts.iter().for_each(|l| {
+        let items = l
+            .equips
+            .iter()
+            .map(|e| e.get_name())
+            .collect::<Vec<_>>()
+            .join(";");
+        let pareto = frontier.iter().any(|f| std::ptr::eq(*f, l));
+        csv.push_str(&format!(
+            "\"{items}\",{},{},{},{:?},{},{},{},{pareto}\n",
+            l.price,
+            l.damage,
+            l.armor,
+            l.report.winner,
+            l.report.rounds,
+            l.report.hero_hp,
+            l.report.boss_hp
+        ));
+    });
+    csv
+}
+
 enum PriceKind {
     Min,
     Max,
@@ -233,7 +325,7 @@ enum Outcome {
     Lose,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct Boss {
     hp: i32,
     damage: i32,
@@ -255,6 +347,7 @@ impl From<String> for Boss {
 }
 
 impl Boss {
+    #[cfg(test)]
     fn hit(&self, hero: &mut Hero) {
         hero.hp -= 1.max(self.damage - hero.get_armor());
     }
@@ -264,34 +357,42 @@ struct Shop {
     items: Vec<Equip>,
 }
 
-impl Shop {
-    fn new() -> Self {
-        let dagger = Equip::new_weapon("Dagger", 8, 4);
-        let shortsword = Equip::new_weapon("Shortsword", 10, 5);
-        let warhammer = Equip::new_weapon("Warhammer", 25, 6);
-        let longsword = Equip::new_weapon("Longsword", 40, 7);
-        let greataxe = Equip::new_weapon("Greataxe", 74, 8);
-        let leather = Equip::new_armor("Leather", 13, 1);
-        let chainmail = Equip::new_armor("Chainmail", 31, 2);
-        let splintmail = Equip::new_armor("Splintmail", 53, 3);
-        let bandedmail = Equip::new_armor("Leather", 75, 4);
-        let platemail = Equip::new_armor("Platemail", 102, 5);
-        let damage_1 = Equip::new_ring("Damage +1", 25, 1, 0);
-        let damage_2 = Equip::new_ring("Damage +2", 50, 2, 0);
-        let damage_3 = Equip::new_ring("Damage +3", 100, 3, 0);
-        let defense_1 = Equip::new_ring("Defense +1", 20, 0, 1);
-        let defense_2 = Equip::new_ring("Defense +2", 40, 0, 2);
-        let defense_3 = Equip::new_ring("Defense +3", 80, 0, 3);
-        Self {
-            items: vec![
-                dagger, shortsword, warhammer, longsword, greataxe, leather, chainmail, splintmail,
-                bandedmail, platemail, damage_1, damage_2, damage_3, defense_1, defense_2,
-                defense_3,
-            ],
-        }
+impl From<&str> for Shop {
+    fn from(input: &str) -> Self {
+        let mut items = Vec::new();
+        let mut category = "";
+        input
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .for_each(|line| {
+                if let Some((header, _columns)) = line.split_once(':') {
+                    category = header.trim();
+                    return;
+                }
+                let mut tokens = line.split_whitespace().collect::<Vec<_>>();
+                if tokens.len() < 4 {
+                    panic!("Invalid shop line {line}");
+                }
+                let stats = tokens
+                    .split_off(tokens.len() - 3)
+                    .iter()
+                    .map(|n| n.parse::<i32>().unwrap())
+                    .collect::<Vec<_>>();
+                let name = tokens.join(" ");
+                let (price, damage, armor) = (stats[0], stats[1], stats[2]);
+                items.push(match category {
+                    "Weapons" => Equip::new_weapon(&name, price, damage),
+                    "Armor" => Equip::new_armor(&name, price, armor),
+                    "Rings" => Equip::new_ring(&name, price, damage, armor),
+                    c => panic!("Unknown shop category {c}"),
+                });
+            });
+        Self { items }
     }
+}
 
-    fn combinations(&self) -> Vec<Vec<Equip>> {
+impl Shop {
+    fn combinations(&self, slots: &SlotRules) -> Vec<Vec<Equip>> {
         fn combine(stock: &[Equip], max_equips: usize) -> Vec<Vec<Equip>> {
             if stock.is_empty() {
                 return vec![vec![]];
@@ -315,20 +416,9 @@ impl Shop {
             result.append(&mut combinations_without_first);
             result
         }
-        let mut combinations = combine(&self.items, 4)
-            .iter()
-            .cloned()
-            .filter(|c| {
-                let (w, a, r) = c.iter().fold((0, 0, 0), |(mut w, mut a, mut r), e| {
-                    match e {
-                        Equip::Weapon(_) => w += 1,
-                        Equip::Armor(_) => a += 1,
-                        Equip::Ring(_) => r += 1,
-                    };
-                    (w, a, r)
-                });
-                w == 1 && a <= 1 && r <= 2
-            })
+        let mut combinations = combine(&self.items, slots.max_equips())
+            .into_iter()
+            .filter(|c| slots.allows(c))
             .collect::<Vec<_>>();
         combinations.sort_by(|c1, c2| {
             let p1: i32 = c1.iter().map(|e| e.get_price()).sum();
@@ -340,16 +430,41 @@ impl Shop {
 }
 
 fn main() {
-    let shop = Shop::new();
-    let mut combinations = shop.combinations();
+    let shop = Shop::from(read_input("day21_shop.txt").as_str());
+    let boss = Boss::from(read_input("day21.txt"));
+    let config = Config::default();
+    let mut combinations = shop.combinations(&config.slots);
     println!(
         "Part 1 = {}",
-        Hero::forecast_price(PriceKind::Min, Outcome::Win, &mut combinations)
+        Hero::forecast_price(
+            PriceKind::Min,
+            Outcome::Win,
+            &mut combinations,
+            &boss,
+            &config
+        )
     );
     println!(
         "Part 2 = {}",
-        Hero::forecast_price(PriceKind::Max, Outcome::Lose, &mut combinations)
+        Hero::forecast_price(
+            PriceKind::Max,
+            Outcome::Lose,
+            &mut combinations,
+            &boss,
+            &config
+        )
+    );
+
+    let loadouts = analyse_loadouts(&combinations, &boss, &config);
+    println!(
+        "Pareto-optimal loadouts = {} of {}",
+        pareto_frontier(&loadouts).len(),
+        loadouts.len()
     );
+    let args = std::env::args().collect::<Vec<_>>();
+    if let Some(idx) = args.iter().position(|arg| arg == "--csv") {
+        fs::write(&args[idx + 1], loadouts_to_csv(&loadouts)).unwrap();
+    }
 }
 
 #[cfg(test)]
@@ -359,17 +474,149 @@ mod day21_tests {
     #[test]
     fn part1() {
         let shortsword = Equip::new_weapon("Shortsword", 10, 5);
-        let bandedmail = Equip::new_armor("Leather", 75, 4);
+        let bandedmail = Equip::new_armor("Bandedmail", 75, 4);
         let defense_1 = Equip::new_ring("Defense +1", 20, 0, 1);
-        let mut hero = Hero::new(8, shortsword)
-            .with_armor(bandedmail)
-            .with_left_ring(defense_1);
+        let mut hero = Hero::new(8)
+            .with_equip(shortsword)
+            .with_equip(bandedmail)
+            .with_equip(defense_1);
         let mut boss = Boss::from(
             r#"Hit Points: 12
 Damage: 7
 Armor: 2"#
                 .to_string(),
         );
+        assert_eq!(
+            hero.fight_outcome(&boss),
+            FightReport {
+                winner: Winner::Hero,
+                rounds: 4,
+                hero_hp: 2,
+                boss_hp: 0,
+            }
+        );
         assert!(hero.fight(&mut boss));
     }
+
+    #[test]
+    fn fight_outcome_matches_simulation() {
+        let shop = Shop::from(SHOP);
+        let combinations = shop.combinations(&SlotRules::default());
+        for boss_hp in 1..=40 {
+            for damage in 1..=10 {
+                for armor in 0..=4 {
+                    let boss = Boss {
+                        hp: boss_hp,
+                        damage,
+                        armor,
+                    };
+                    for c in &combinations {
+                        let hero = c.iter().cloned().fold(Hero::new(30), Hero::with_equip);
+                        let report = hero.fight_outcome(&boss);
+                        let mut simulated_hero =
+                            c.iter().cloned().fold(Hero::new(30), Hero::with_equip);
+                        let mut simulated_boss = boss.clone();
+                        let hero_won = simulated_hero.fight(&mut simulated_boss);
+                        assert_eq!(report.winner == Winner::Hero, hero_won);
+                        assert_eq!(report.hero_hp, simulated_hero.hp);
+                        assert_eq!(report.boss_hp, simulated_boss.hp);
+                    }
+                }
+            }
+        }
+    }
+
+    const SHOP: &str = r#"Weapons:    Cost  Damage  Armor
+Dagger        8     4       0
+Greataxe     74     8       0
+
+Armor:      Cost  Damage  Armor
+Bandedmail   75     0       4
+
+Rings:      Cost  Damage  Armor
+Damage +1    25     1       0
+Defense +3   80     0       3"#;
+
+    #[test]
+    fn parse_shop() {
+        let shop = Shop::from(SHOP);
+        assert_eq!(shop.items.len(), 5);
+        let Equip::Armor(bandedmail) = &shop.items[2] else {
+            panic!("Expected armor");
+        };
+        assert_eq!(bandedmail.name, "Bandedmail");
+        assert_eq!(bandedmail.armor, 4);
+        let Equip::Ring(ring) = &shop.items[3] else {
+            panic!("Expected a ring");
+        };
+        assert_eq!(ring.name, "Damage +1");
+        assert_eq!((ring.price, ring.damage, ring.armor), (25, 1, 0));
+    }
+
+    #[test]
+    fn slot_rules() {
+        let shop = Shop::from(SHOP);
+        // 2 weapons x 2 armor choices x 4 ring choices
+        assert_eq!(shop.combinations(&SlotRules::default()).len(), 16);
+        let rules = SlotRules {
+            weapons: 1..=1,
+            armor: 1..=1,
+            rings: 0..=0,
+        };
+        let combinations = shop.combinations(&rules);
+        assert_eq!(combinations.len(), 2);
+        assert!(combinations.iter().all(|c| rules.allows(c)));
+    }
+
+    #[test]
+    fn pareto() {
+        let shop = Shop::from(SHOP);
+        let boss = Boss {
+            hp: 12,
+            damage: 7,
+            armor: 2,
+        };
+        let config = Config {
+            hero_hp: 8,
+            ..Default::default()
+        };
+        let loadouts = analyse_loadouts(&shop.combinations(&config.slots), &boss, &config);
+        assert_eq!(loadouts.len(), 16);
+        let frontier = pareto_frontier(&loadouts);
+        assert_eq!(
+            frontier
+                .iter()
+                .map(|f| (f.price, f.damage, f.armor))
+                .collect::<Vec<_>>(),
+            vec![
+                (8, 4, 0),
+                (33, 5, 0),
+                (74, 8, 0),
+                (83, 4, 4),
+                (99, 9, 0),
+                (108, 5, 4),
+                (149, 8, 4),
+                (163, 4, 7),
+                (174, 9, 4),
+                (188, 5, 7),
+                (229, 8, 7),
+                (254, 9, 7),
+            ]
+        );
+        // Dagger + Defense +3 costs more than Dagger + Bandedmail for less armor.
+        assert!(loadouts
+            .iter()
+            .any(|l| (l.price, l.damage, l.armor) == (88, 4, 3)));
+        assert!(!frontier
+            .iter()
+            .any(|f| (f.price, f.damage, f.armor) == (88, 4, 3)));
+        let csv = loadouts_to_csv(&loadouts);
+        assert_eq!(csv.lines().count(), 17);
+        assert!(csv.starts_with("items,price,damage,armor,winner,rounds,hero_hp,boss_hp,pareto\n"));
+        assert!(csv.contains("\"Dagger\",8,4,0,Boss,"));
+        assert_eq!(
+            csv.lines().filter(|line| line.ends_with(",true")).count(),
+            frontier.len()
+        );
+    }
 }
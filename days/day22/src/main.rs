@@ -1,6 +1,13 @@
-use std::{collections::HashMap, fmt};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    fmt, fs,
+    io::{self, BufRead, Write},
+};
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+use common::read_input;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 struct Character {
     hp: i32,
     mana: i32,
@@ -8,312 +15,1153 @@ struct Character {
     damage: i32,
 }
 
-#[derive(Clone, Debug)]
+impl From<String> for Character {
+    fn from(input: String) -> Self {
+        let mut character = Character {
+            hp: 0,
+            mana: 0,
+            armor: 0,
+            damage: 0,
+        };
+        input.lines().for_each(|line| {
+            let (stat, value) = line.split_once(": ").unwrap();
+            let value = value.trim().parse().unwrap();
+            match stat {
+                "Hit Points" => character.hp = value,
+                "Damage" => character.damage = value,
+                "Armor" => character.armor = value,
+                "Mana" => character.mana = value,
+                s => panic!("Unknown stat {s}"),
+            }
+        });
+        character
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 struct Spell {
     name: String,
     cost: i32,
     effect: Effect,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 struct Effect {
     duration: i32,
-    apply: CloneableFnBox,
-    revert: CloneableFnBox,
+    damage: i32,
+    heal: i32,
+    turn_damage: i32,
+    armor: i32,
+    mana: i32,
 }
 
-trait CloneableFn: Fn(&mut Character, &mut Character) + 'static {
-    fn clone_box(&self) -> Box<dyn CloneableFn>;
-}
+impl Effect {
+    fn cast(&self, player: &mut Character, boss: &mut Character) {
+        boss.hp -= self.damage;
+        player.hp += self.heal;
+        if self.duration > 0 {
+            player.armor += self.armor;
+        }
+    }
+
+    fn apply(&self, player: &mut Character, boss: &mut Character) {
+        boss.hp -= self.turn_damage;
+        player.mana += self.mana;
+    }
 
-impl<T> CloneableFn for T
-where
-    T: Fn(&mut Character, &mut Character) + Clone + 'static,
-{
-    fn clone_box(&self) -> Box<dyn CloneableFn> {
-        Box::new(self.clone())
+    fn revert(&self, player: &mut Character) {
+        player.armor -= self.armor;
     }
 }
 
-struct CloneableFnBox {
-    inner: Box<dyn CloneableFn>,
+const DEFAULT_SPELLS: &str = r#"[[spell]]
+name = "Magic Missile"
+cost = 53
+damage = 4
+
+[[spell]]
+name = "Drain"
+cost = 73
+damage = 2
+heal = 2
+
+[[spell]]
+name = "Shield"
+cost = 113
+duration = 6
+armor = 7
+
+[[spell]]
+name = "Poison"
+cost = 173
+duration = 6
+turn_damage = 3
+
+[[spell]]
+name = "Recharge"
+cost = 229
+duration = 5
+mana = 101
+"#;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct SpellBook(Vec<Spell>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SpellBookError {
+    Syntax { line: usize, text: String },
+    UnknownKey { line: usize, key: String },
+    InvalidValue { line: usize, value: String },
+    MissingName,
+    InvalidDuration(String),
+    TooManySpells,
 }
 
-impl CloneableFnBox {
-    fn call(&self, player: &mut Character, boss: &mut Character) {
-        (self.inner)(player, boss);
+impl fmt::Display for SpellBookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpellBookError::Syntax { line, text } => write!(f, "line {line}: cannot parse {text}"),
+            SpellBookError::UnknownKey { line, key } => {
+                write!(f, "line {line}: unknown spell property {key}")
+            }
+            SpellBookError::InvalidValue { line, value } => {
+                write!(f, "line {line}: invalid value {value}")
+            }
+            SpellBookError::MissingName => write!(f, "spell without a name"),
+            SpellBookError::InvalidDuration(name) => write!(f, "invalid duration for spell {name}"),
+            SpellBookError::TooManySpells => write!(f, "at most {MAX_SPELLS} spells are supported"),
+        }
     }
 }
 
-impl Clone for CloneableFnBox {
-    fn clone(&self) -> Self {
-        CloneableFnBox {
-            inner: self.inner.clone_box(),
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (idx, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..idx],
+            _ => {}
         }
     }
+    line
 }
 
-impl fmt::Debug for CloneableFnBox {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "<function>")
+fn parse_string(value: &str) -> Option<String> {
+    let inner = value.strip_prefix('"')?.strip_suffix('"')?;
+    let mut string = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next()? {
+                '"' => string.push('"'),
+                '\\' => string.push('\\'),
+                _ => return None,
+            },
+            '"' => return None,
+            c => string.push(c),
+        }
     }
+    Some(string)
 }
 
-impl fmt::Debug for Effect {
+// Reads the TOML subset used by DEFAULT_SPELLS: `[[spell]]` tables holding a
+// quoted `name` and integer properties, with `#` comments.
+impl TryFrom<&str> for SpellBook {
+    type Error = SpellBookError;
+
+    fn try_from(input: &str) -> Result<Self, Self::Error> {
+        let mut spells: Vec<Spell> = Vec::new();
+        for (idx, line) in input.lines().enumerate() {
+            let line_number = idx + 1;
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line == "[[spell]]" {
+                spells.push(Spell::default());
+                continue;
+            }
+            let syntax_error = || SpellBookError::Syntax {
+                line: line_number,
+                text: line.to_string(),
+            };
+            let spell = spells.last_mut().ok_or_else(syntax_error)?;
+            let (key, value) = line.split_once('=').ok_or_else(syntax_error)?;
+            let value = value.trim();
+            let invalid_value = || SpellBookError::InvalidValue {
+                line: line_number,
+                value: value.to_string(),
+            };
+            let number = || value.parse::<i32>().map_err(|_| invalid_value());
+            match key.trim() {
+                "name" => spell.name = parse_string(value).ok_or_else(invalid_value)?,
+                "cost" => spell.cost = number()?,
+                "duration" => spell.effect.duration = number()?,
+                "damage" => spell.effect.damage = number()?,
+                "heal" => spell.effect.heal = number()?,
+                "turn_damage" => spell.effect.turn_damage = number()?,
+                "armor" => spell.effect.armor = number()?,
+                "mana" => spell.effect.mana = number()?,
+                key => {
+                    return Err(SpellBookError::UnknownKey {
+                        line: line_number,
+                        key: key.to_string(),
+                    })
+                }
+            }
+        }
+        if spells.iter().any(|spell| spell.name.is_empty()) {
+            return Err(SpellBookError::MissingName);
+        }
+        if let Some(spell) = spells
+            .iter()
+            .find(|spell| !(0..=u8::MAX as i32).contains(&spell.effect.duration))
+        {
+            return Err(SpellBookError::InvalidDuration(spell.name.clone()));
+        }
+        if spells.len() > MAX_SPELLS {
+            return Err(SpellBookError::TooManySpells);
+        }
+        Ok(SpellBook(spells))
+    }
+}
+
+impl fmt::Display for SpellBook {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("Effect")
-            .field("duration", &self.duration)
-            .finish()
+        for (idx, spell) in self.0.iter().enumerate() {
+            if idx > 0 {
+                writeln!(f)?;
+            }
+            writeln!(f, "[[spell]]")?;
+            writeln!(
+                f,
+                "name = \"{}\"",
+                spell.name.replace('\\', "\\\\").replace('"', "\\\"")
+            )?;
+            writeln!(f, "cost = {}", spell.cost)?;
+            let effect = &spell.effect;
+            [
+                ("duration", effect.duration),
+                ("damage", effect.damage),
+                ("heal", effect.heal),
+                ("turn_damage", effect.turn_damage),
+                ("armor", effect.armor),
+                ("mana", effect.mana),
+            ]
+            .iter()
+            .filter(|(_, value)| *value != 0)
+            .try_for_each(|(key, value)| writeln!(f, "{key} = {value}"))?;
+        }
+        Ok(())
     }
 }
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq)]
-struct GameStateKey {
+const MAX_SPELLS: usize = 8;
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+struct StateKey {
     player_hp: i32,
-    player_armor: i32,
     boss_hp: i32,
     mana: i32,
-    effects: Vec<(String, i32)>,
+    timers: [u8; MAX_SPELLS],
     is_player_turn: bool,
-    hard_mode: bool,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug)]
 struct GameState {
     player: Character,
     boss: Character,
     mana_spent: i32,
-    active_effects: Vec<(String, Effect)>,
+    timers: [u8; MAX_SPELLS],
     is_player_turn: bool,
     hard_mode: bool,
 }
 
 impl GameState {
-    fn to_key(&self) -> GameStateKey {
-        GameStateKey {
+    fn new(player: Character, boss: Character, hard_mode: bool) -> Self {
+        Self {
+            player,
+            boss,
+            mana_spent: 0,
+            timers: [0; MAX_SPELLS],
+            is_player_turn: true,
+            hard_mode,
+        }
+    }
+
+    fn key(&self) -> StateKey {
+        StateKey {
             player_hp: self.player.hp,
-            player_armor: self.player.armor,
             boss_hp: self.boss.hp,
             mana: self.player.mana,
-            effects: self
-                .active_effects
-                .iter()
-                .map(|(name, effect)| (name.clone(), effect.duration))
-                .collect(),
+            timers: self.timers,
             is_player_turn: self.is_player_turn,
-            hard_mode: self.hard_mode,
         }
     }
+
+    fn active_effects(&self, spells: &[Spell]) -> Vec<(String, i32)> {
+        spells
+            .iter()
+            .zip(self.timers)
+            .filter(|(_, timer)| *timer > 0)
+            .map(|(spell, timer)| (spell.name.clone(), timer as i32))
+            .collect()
+    }
 }
 
-fn simulate_turn(
-    mut state: GameState,
-    spells: &[Spell],
-    memo: &mut HashMap<GameStateKey, Option<i32>>,
-) -> Option<i32> {
-    let key = state.to_key();
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Outcome {
+    Won,
+    Lost,
+}
+
+#[derive(Clone, Debug)]
+struct TurnLog {
+    turn: usize,
+    is_player_turn: bool,
+    player_hp: i32,
+    player_armor: i32,
+    mana: i32,
+    boss_hp: i32,
+    effects: Vec<(String, i32)>,
+    action: Action,
+}
 
-    if let Some(cached) = memo.get(&key) {
-        return cached.clone();
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Action {
+    Cast(String),
+    BossAttack(i32),
+    Finished(Outcome),
+}
+
+impl fmt::Display for TurnLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let who = if self.is_player_turn {
+            "Player"
+        } else {
+            "Boss"
+        };
+        writeln!(f, "-- {who} turn {} --", self.turn)?;
+        writeln!(
+            f,
+            "- Player has {} hit points, {} armor, {} mana",
+            self.player_hp, self.player_armor, self.mana
+        )?;
+        writeln!(f, "- Boss has {} hit points", self.boss_hp)?;
+        let effects = self
+            .effects
+            .iter()
+            .map(|(name, timer)| format!("{name} ({timer})"))
+            .collect::<Vec<_>>();
+        if !effects.is_empty() {
+            writeln!(f, "- Active effects: {}", effects.join(", "))?;
+        }
+        match &self.action {
+            Action::Cast(spell) => write!(f, "Player casts {spell}."),
+            Action::BossAttack(damage) => write!(f, "Boss attacks for {damage} damage."),
+            Action::Finished(Outcome::Won) => {
+                write!(f, "This kills the boss, and the player wins.")
+            }
+            Action::Finished(Outcome::Lost) => write!(f, "The player dies."),
+        }
     }
+}
+
+#[derive(Clone, Debug)]
+struct Solution {
+    mana_spent: i32,
+    spells: Vec<String>,
+    log: Vec<TurnLog>,
+    expanded: usize,
+}
 
+fn begin_turn(state: &mut GameState, spells: &[Spell]) -> Option<Outcome> {
     if state.is_player_turn && state.hard_mode {
         state.player.hp -= 1;
     }
 
-    apply_effects(&mut state);
+    apply_effects(state, spells);
 
     if state.player.hp <= 0 {
-        return None;
+        return Some(Outcome::Lost);
     }
 
     if state.boss.hp <= 0 {
-        return Some(state.mana_spent);
+        return Some(Outcome::Won);
     }
 
-    let mut results = Vec::new();
-    if state.is_player_turn {
-        let available_spells = spells
+    None
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CastError {
+    AlreadyActive,
+    NotEnoughMana,
+    UnknownSpell,
+}
+
+impl fmt::Display for CastError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CastError::AlreadyActive => write!(f, "the effect is still active"),
+            CastError::NotEnoughMana => write!(f, "not enough mana"),
+            CastError::UnknownSpell => write!(f, "unknown spell"),
+        }
+    }
+}
+
+fn check_cast(state: &GameState, spells: &[Spell], spell: usize) -> Result<(), CastError> {
+    if state.timers[spell] > 0 {
+        Err(CastError::AlreadyActive)
+    } else if spells[spell].cost > state.player.mana {
+        Err(CastError::NotEnoughMana)
+    } else {
+        Ok(())
+    }
+}
+
+fn can_cast(state: &GameState, spells: &[Spell], spell: usize) -> bool {
+    check_cast(state, spells, spell).is_ok()
+}
+
+fn cast(state: &mut GameState, spells: &[Spell], spell: usize) {
+    let spell_data = &spells[spell];
+    state.player.mana -= spell_data.cost;
+    state.mana_spent += spell_data.cost;
+    state.is_player_turn = false;
+    spell_data.effect.cast(&mut state.player, &mut state.boss);
+    state.timers[spell] = spell_data.effect.duration as u8;
+}
+
+fn boss_attack(state: &mut GameState) -> i32 {
+    let damage = (state.boss.damage - state.player.armor).max(1);
+    state.player.hp -= damage;
+    state.is_player_turn = true;
+    damage
+}
+
+struct SearchNode {
+    state: GameState,
+    parent: Option<usize>,
+    spell: Option<usize>,
+}
+
+// Dijkstra on mana spent: the first winning state popped from the queue is the
+// cheapest one. Returns the spells cast to reach it and the number of states
+// expanded along the way.
+fn find_cheapest_win(
+    initial_state: GameState,
+    spells: &[Spell],
+) -> (Option<(i32, Vec<usize>)>, usize) {
+    let mut nodes = vec![SearchNode {
+        state: initial_state,
+        parent: None,
+        spell: None,
+    }];
+    let mut best = HashMap::from([(initial_state.key(), 0)]);
+    let mut queue = BinaryHeap::from([Reverse((0, 0))]);
+    let mut expanded = 0;
+
+    while let Some(Reverse((mana_spent, idx))) = queue.pop() {
+        let state = nodes[idx].state;
+        if best
+            .get(&state.key())
+            .is_some_and(|&best| best < mana_spent)
+        {
+            continue;
+        }
+        expanded += 1;
+
+        let mut started = state;
+        match begin_turn(&mut started, spells) {
+            Some(Outcome::Won) => {
+                let mut sequence = Vec::new();
+                let mut current = Some(idx);
+                while let Some(node) = current {
+                    sequence.extend(nodes[node].spell);
+                    current = nodes[node].parent;
+                }
+                sequence.reverse();
+                return (Some((mana_spent, sequence)), expanded);
+            }
+            Some(Outcome::Lost) => continue,
+            None => (),
+        }
+
+        let mut successors = Vec::new();
+        if started.is_player_turn {
+            for spell in (0..spells.len()).filter(|&spell| can_cast(&started, spells, spell)) {
+                let mut next = started;
+                cast(&mut next, spells, spell);
+                successors.push((next, Some(spell)));
+            }
+        } else {
+            let mut next = started;
+            boss_attack(&mut next);
+            successors.push((next, None));
+        }
+
+        for (next, spell) in successors {
+            let key = next.key();
+            if best.get(&key).is_some_and(|&best| best <= next.mana_spent) {
+                continue;
+            }
+            best.insert(key, next.mana_spent);
+            nodes.push(SearchNode {
+                state: next,
+                parent: Some(idx),
+                spell,
+            });
+            queue.push(Reverse((next.mana_spent, nodes.len() - 1)));
+        }
+    }
+
+    (None, expanded)
+}
+
+fn fight_log(mut state: GameState, spells: &[Spell], sequence: &[usize]) -> Vec<TurnLog> {
+    let mut log = Vec::new();
+    let mut sequence = sequence.iter();
+    let mut turn = 1;
+    loop {
+        let mut entry = TurnLog {
+            turn,
+            is_player_turn: state.is_player_turn,
+            player_hp: state.player.hp,
+            player_armor: state.player.armor,
+            mana: state.player.mana,
+            boss_hp: state.boss.hp,
+            effects: state.active_effects(spells),
+            action: Action::Finished(Outcome::Lost),
+        };
+        if let Some(outcome) = begin_turn(&mut state, spells) {
+            entry.action = Action::Finished(outcome);
+            log.push(entry);
+            return log;
+        }
+        if state.is_player_turn {
+            let Some(&spell) = sequence.next() else {
+                return log;
+            };
+            cast(&mut state, spells, spell);
+            entry.action = Action::Cast(spells[spell].name.clone());
+        } else {
+            entry.action = Action::BossAttack(boss_attack(&mut state));
+        }
+        log.push(entry);
+        turn += 1;
+    }
+}
+
+fn solve(initial_state: GameState, spells: &[Spell]) -> Option<Solution> {
+    let (result, expanded) = find_cheapest_win(initial_state, spells);
+    let (mana_spent, sequence) = result?;
+    let log = fight_log(initial_state, spells, &sequence);
+    Some(Solution {
+        mana_spent,
+        spells: sequence
             .iter()
-            .filter(|spell| {
-                spell.cost <= state.player.mana
-                    && state
-                        .active_effects
-                        .iter()
-                        .all(|(active_name, _effect)| *active_name != spell.name)
-            })
-            .collect::<Vec<&Spell>>();
-        for spell in available_spells {
-            let mut new_state = state.clone();
-            new_state.player.mana -= spell.cost;
-            new_state.mana_spent += spell.cost;
-            new_state.is_player_turn = false;
-
-            new_state
-                .active_effects
-                .push((spell.name.clone(), spell.effect.clone()));
-
-            let result = simulate_turn(new_state, spells, memo);
-            if let Some(cost) = result {
-                results.push(cost);
+            .map(|&spell| spells[spell].name.clone())
+            .collect(),
+        log,
+        expanded,
+    })
+}
+
+fn apply_effects(state: &mut GameState, spells: &[Spell]) {
+    for (spell, timer) in spells.iter().zip(state.timers.iter_mut()) {
+        if *timer > 0 {
+            spell.effect.apply(&mut state.player, &mut state.boss);
+            *timer -= 1;
+            if *timer == 0 {
+                spell.effect.revert(&mut state.player);
             }
         }
-    } else {
-        state.player.hp -= (state.boss.damage - state.player.armor).max(1);
-        state.is_player_turn = true;
-        let result = simulate_turn(state, spells, memo);
-        if let Some(cost) = result {
-            results.push(cost);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Replay {
+    Won {
+        turn: usize,
+        mana_spent: i32,
+    },
+    Lost {
+        turn: usize,
+    },
+    Illegal {
+        turn: usize,
+        spell: String,
+        error: CastError,
+    },
+    Incomplete {
+        turn: usize,
+    },
+}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Replay::Won { turn, mana_spent } => {
+                write!(f, "won on turn {turn} spending {mana_spent} mana")
+            }
+            Replay::Lost { turn } => write!(f, "lost on turn {turn}"),
+            Replay::Illegal { turn, spell, error } => {
+                write!(f, "illegal cast of {spell} on turn {turn}: {error}")
+            }
+            Replay::Incomplete { turn } => {
+                write!(
+                    f,
+                    "ran out of spells on turn {turn} with the fight undecided"
+                )
+            }
         }
     }
+}
 
-    let result = results.into_iter().min();
-    memo.insert(key, result);
-    result
+fn replay(mut state: GameState, spells: &[Spell], sequence: &[&str]) -> Replay {
+    let mut sequence = sequence.iter();
+    let mut turn = 1;
+    loop {
+        match begin_turn(&mut state, spells) {
+            Some(Outcome::Won) => {
+                return Replay::Won {
+                    turn,
+                    mana_spent: state.mana_spent,
+                }
+            }
+            Some(Outcome::Lost) => return Replay::Lost { turn },
+            None => (),
+        }
+        if state.is_player_turn {
+            let Some(&name) = sequence.next() else {
+                return Replay::Incomplete { turn };
+            };
+            let spell = spells
+                .iter()
+                .position(|spell| spell.name == name)
+                .ok_or(CastError::UnknownSpell)
+                .and_then(|spell| check_cast(&state, spells, spell).map(|_| spell));
+            match spell {
+                Ok(spell) => {
+                    cast(&mut state, spells, spell);
+                    if state.boss.hp <= 0 {
+                        return Replay::Won {
+                            turn,
+                            mana_spent: state.mana_spent,
+                        };
+                    }
+                }
+                Err(error) => {
+                    return Replay::Illegal {
+                        turn,
+                        spell: name.to_string(),
+                        error,
+                    }
+                }
+            }
+        } else {
+            boss_attack(&mut state);
+            if state.player.hp <= 0 {
+                return Replay::Lost { turn };
+            }
+        }
+        turn += 1;
+    }
+}
+
+fn write_state(output: &mut impl Write, state: &GameState, spells: &[Spell]) -> io::Result<()> {
+    writeln!(
+        output,
+        "- Player has {} hit points, {} armor, {} mana",
+        state.player.hp, state.player.armor, state.player.mana
+    )?;
+    writeln!(output, "- Boss has {} hit points", state.boss.hp)?;
+    let effects = state
+        .active_effects(spells)
+        .iter()
+        .map(|(name, timer)| format!("{name} ({timer})"))
+        .collect::<Vec<_>>();
+    if !effects.is_empty() {
+        writeln!(output, "- Active effects: {}", effects.join(", "))?;
+    }
+    Ok(())
 }
 
-fn apply_effects(state: &mut GameState) {
-    state.active_effects.retain_mut(|(_, effect)| {
-        effect.apply.call(&mut state.player, &mut state.boss);
-        effect.duration -= 1;
-        if effect.duration <= 0 {
-            effect.revert.call(&mut state.player, &mut state.boss);
-            false
+fn play(
+    mut state: GameState,
+    spells: &[Spell],
+    input: impl BufRead,
+    output: &mut impl Write,
+) -> io::Result<Option<Outcome>> {
+    let mut lines = input.lines();
+    let mut turn = 1;
+    loop {
+        let turn_start = state;
+        let who = if state.is_player_turn {
+            "Player"
+        } else {
+            "Boss"
+        };
+        writeln!(output, "-- {who} turn {turn} --")?;
+        write_state(output, &state, spells)?;
+        let outcome = begin_turn(&mut state, spells);
+        writeln!(output, "After effects:")?;
+        write_state(output, &state, spells)?;
+        if let Some(outcome) = outcome {
+            match outcome {
+                Outcome::Won => writeln!(output, "The boss dies, you win!")?,
+                Outcome::Lost => writeln!(output, "You die.")?,
+            }
+            return Ok(Some(outcome));
+        }
+
+        if state.is_player_turn {
+            if !(0..spells.len()).any(|spell| can_cast(&state, spells, spell)) {
+                writeln!(output, "You cannot afford any spell. You die.")?;
+                return Ok(Some(Outcome::Lost));
+            }
+            loop {
+                let options = spells
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, spell)| format!("[{}] {} ({})", idx + 1, spell.name, spell.cost))
+                    .collect::<Vec<_>>();
+                writeln!(output, "Cast: {}, hint, quit", options.join(", "))?;
+                output.flush()?;
+                let Some(line) = lines.next() else {
+                    return Ok(None);
+                };
+                let command = line?.trim().to_lowercase();
+                if command == "quit" {
+                    return Ok(None);
+                }
+                if command == "hint" {
+                    match solve(turn_start, spells) {
+                        Some(solution) => writeln!(
+                            output,
+                            "Hint: {} (costs {} more mana)",
+                            solution.spells.join(", "),
+                            solution.mana_spent - turn_start.mana_spent
+                        )?,
+                        None => writeln!(output, "Hint: there is no way to win from here")?,
+                    }
+                    continue;
+                }
+                let spell = command
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|n| n.checked_sub(1))
+                    .filter(|&idx| idx < spells.len())
+                    .or_else(|| {
+                        spells
+                            .iter()
+                            .position(|spell| spell.name.to_lowercase() == command)
+                    });
+                let Some(spell) = spell else {
+                    writeln!(output, "Unknown spell {command}")?;
+                    continue;
+                };
+                match check_cast(&state, spells, spell) {
+                    Ok(()) => {
+                        cast(&mut state, spells, spell);
+                        writeln!(output, "Player casts {}.", spells[spell].name)?;
+                        break;
+                    }
+                    Err(error) => writeln!(output, "Cannot cast {}: {error}", spells[spell].name)?,
+                }
+            }
         } else {
-            true
+            let damage = boss_attack(&mut state);
+            writeln!(output, "Boss attacks for {damage} damage.")?;
         }
-    });
+        write_state(output, &state, spells)?;
+        writeln!(output)?;
+        turn += 1;
+    }
+}
+
+fn print_solution(solution: &Solution) {
+    if std::env::args().any(|arg| arg == "--log") {
+        solution.log.iter().for_each(|entry| println!("{entry}\n"));
+    }
+    println!("Spells: {}", solution.spells.join(", "));
+    println!("States expanded: {}", solution.expanded);
 }
 
 fn main() {
-    let spells = vec![
-        Spell {
-            name: "Magic Missile".to_owned(),
-            cost: 53,
-            effect: Effect {
-                duration: 0,
-                apply: CloneableFnBox {
-                    inner: Box::new(|_player: &mut Character, boss: &mut Character| boss.hp -= 4),
-                },
-                revert: CloneableFnBox {
-                    inner: Box::new(|_player: &mut Character, _boss: &mut Character| {}),
-                },
-            },
-        },
-        Spell {
-            name: "Drain".to_owned(),
-            cost: 73,
-            effect: Effect {
-                duration: 0,
-                apply: CloneableFnBox {
-                    inner: Box::new(|player: &mut Character, boss: &mut Character| {
-                        player.hp += 2;
-                        boss.hp -= 2;
-                    }),
-                },
-                revert: CloneableFnBox {
-                    inner: Box::new(|_player: &mut Character, _boss: &mut Character| {}),
-                },
-            },
-        },
-        Spell {
-            name: "Shield".to_owned(),
-            cost: 113,
-            effect: Effect {
-                duration: 6,
-                apply: CloneableFnBox {
-                    inner: Box::new(|player: &mut Character, _boss: &mut Character| {
-                        player.armor = 7
-                    }),
-                },
-                revert: CloneableFnBox {
-                    inner: Box::new(|player: &mut Character, _boss: &mut Character| {
-                        player.armor = 0
-                    }),
-                },
-            },
-        },
-        Spell {
-            name: "Poison".to_owned(),
-            cost: 173,
-            effect: Effect {
-                duration: 6,
-                apply: CloneableFnBox {
-                    inner: Box::new(|_player: &mut Character, boss: &mut Character| {
-                        boss.hp -= 3;
-                    }),
-                },
-                revert: CloneableFnBox {
-                    inner: Box::new(|_player: &mut Character, _boss: &mut Character| {}),
-                },
-            },
-        },
-        Spell {
-            name: "Recharge".to_owned(),
-            cost: 229,
-            effect: Effect {
-                duration: 5,
-                apply: CloneableFnBox {
-                    inner: Box::new(|player: &mut Character, _boss: &mut Character| {
-                        player.mana += 101
-                    }),
-                },
-                revert: CloneableFnBox {
-                    inner: Box::new(|_player: &mut Character, _boss: &mut Character| {}),
+    let args = std::env::args().collect::<Vec<_>>();
+    let spells = match args.iter().position(|arg| arg == "--spells") {
+        Some(idx) => SpellBook::try_from(fs::read_to_string(&args[idx + 1]).unwrap().as_str()),
+        None => SpellBook::try_from(DEFAULT_SPELLS),
+    }
+    .unwrap_or_else(|error| panic!("Invalid spell book: {error}"));
+    let spells = spells.0;
+    let player = Character {
+        hp: 50,
+        mana: 500,
+        armor: 0,
+        damage: 0,
+    };
+    let boss = Character::from(read_input("day22.txt"));
+
+    if let Some(idx) = args.iter().position(|arg| arg == "--replay") {
+        let hard_mode = args.iter().any(|arg| arg == "--hard");
+        let sequence = args[idx + 1].split(',').map(str::trim).collect::<Vec<_>>();
+        let state = GameState::new(player, boss, hard_mode);
+        println!("Replay {}", replay(state, &spells, &sequence));
+        return;
+    }
+
+    if args.iter().any(|arg| arg == "--play") {
+        let hard_mode = args.iter().any(|arg| arg == "--hard");
+        let state = GameState::new(player, boss, hard_mode);
+        play(state, &spells, io::stdin().lock(), &mut io::stdout()).unwrap();
+        return;
+    }
+
+    let initial_state = GameState::new(player, boss, false);
+
+    if let Some(solution) = solve(initial_state, &spells) {
+        print_solution(&solution);
+        println!("Part 1: {}", solution.mana_spent);
+    } else {
+        println!("No solution found.");
+    }
+
+    let initial_state = GameState::new(player, boss, true);
+
+    if let Some(solution) = solve(initial_state, &spells) {
+        print_solution(&solution);
+        println!("Part 2: {}", solution.mana_spent);
+    } else {
+        println!("No solution found.");
+    }
+}
+
+#[cfg(test)]
+mod day22_tests {
+    use parameterized::parameterized;
+
+    use super::*;
+
+    fn example_state(boss_hp: i32) -> GameState {
+        let player = Character {
+            hp: 10,
+            mana: 250,
+            armor: 0,
+            damage: 0,
+        };
+        let boss = Character {
+            hp: boss_hp,
+            mana: 0,
+            armor: 0,
+            damage: 8,
+        };
+        GameState::new(player, boss, false)
+    }
+
+    #[test]
+    fn part1() {
+        let solution = solve(
+            example_state(13),
+            &SpellBook::try_from(DEFAULT_SPELLS).unwrap().0,
+        )
+        .unwrap();
+        assert_eq!(solution.mana_spent, 226);
+        assert_eq!(solution.spells, vec!["Poison", "Magic Missile"]);
+        assert_eq!(solution.log.len(), 4);
+        assert_eq!(solution.log[1].action, Action::BossAttack(8));
+        assert_eq!(solution.log[2].player_hp, 2);
+        assert_eq!(solution.log[2].effects, vec![("Poison".to_string(), 5)]);
+        assert_eq!(solution.log[3].action, Action::Finished(Outcome::Won));
+    }
+
+    #[test]
+    fn part1_second_example() {
+        let solution = solve(
+            example_state(14),
+            &SpellBook::try_from(DEFAULT_SPELLS).unwrap().0,
+        )
+        .unwrap();
+        assert_eq!(solution.mana_spent, 641);
+        assert_eq!(
+            solution.spells,
+            vec!["Recharge", "Shield", "Drain", "Poison", "Magic Missile"]
+        );
+        let last = solution.log.last().unwrap();
+        assert_eq!(last.boss_hp, 2);
+        assert_eq!(last.player_hp, 1);
+        assert_eq!(last.action, Action::Finished(Outcome::Won));
+    }
+
+    #[test]
+    fn spell_book_round_trip() {
+        let book = SpellBook::try_from(DEFAULT_SPELLS).unwrap();
+        assert_eq!(book.0.len(), 5);
+        assert_eq!(
+            book.0[2],
+            Spell {
+                name: "Shield".to_string(),
+                cost: 113,
+                effect: Effect {
+                    duration: 6,
+                    armor: 7,
+                    ..Default::default()
                 },
-            },
-        },
-    ];
+            }
+        );
+        assert_eq!(book.to_string(), DEFAULT_SPELLS);
+        assert_eq!(SpellBook::try_from(book.to_string().as_str()), Ok(book));
+    }
 
-    let initial_state = GameState {
-        player: Character {
-            hp: 50,
-            mana: 500,
+    #[test]
+    fn custom_spell() {
+        let book = SpellBook::try_from(
+            r#"[[spell]]
+name = "Fireball" # big and expensive
+cost = 100
+damage = 13"#,
+        )
+        .unwrap();
+        let solution = solve(example_state(13), &book.0).unwrap();
+        assert_eq!(solution.spells, vec!["Fireball"]);
+    }
+
+    #[test]
+    fn spell_book_names_and_errors() {
+        let book = SpellBook::try_from(
+            r#"# spells with awkward names
+[[spell]]
+name = "C# Bolt" # a comment after a hash in the name
+cost = 10
+damage = 1
+
+[[spell]]
+name = "The \"Big\" One"
+cost = 20
+damage = 2"#,
+        )
+        .unwrap();
+        assert_eq!(book.0[0].name, "C# Bolt");
+        assert_eq!(book.0[1].name, "The \"Big\" One");
+        assert_eq!(SpellBook::try_from(book.to_string().as_str()), Ok(book));
+
+        assert_eq!(
+            SpellBook::try_from("[[spell]]\nname = \"Zap\"\nrange = 3"),
+            Err(SpellBookError::UnknownKey {
+                line: 3,
+                key: "range".to_string()
+            })
+        );
+        assert_eq!(
+            SpellBook::try_from("cost = 3"),
+            Err(SpellBookError::Syntax {
+                line: 1,
+                text: "cost = 3".to_string()
+            })
+        );
+        assert_eq!(
+            SpellBook::try_from("[[spell]]\nname = Zap"),
+            Err(SpellBookError::InvalidValue {
+                line: 2,
+                value: "Zap".to_string()
+            })
+        );
+        assert_eq!(
+            SpellBook::try_from("[[spell]]\ncost = lots"),
+            Err(SpellBookError::InvalidValue {
+                line: 2,
+                value: "lots".to_string()
+            })
+        );
+        assert_eq!(
+            SpellBook::try_from("[[spell]]\ncost = 3"),
+            Err(SpellBookError::MissingName)
+        );
+        assert_eq!(
+            SpellBook::try_from("[[spell]]\nname = \"Slow\"\nduration = 300")
+                .unwrap_err()
+                .to_string(),
+            "invalid duration for spell Slow"
+        );
+    }
+
+    fn high_hp_state() -> GameState {
+        let player = Character {
+            hp: 100,
+            mana: 1000,
             armor: 0,
             damage: 0,
-        },
-        boss: Character {
-            hp: 51,
+        };
+        let boss = Character {
+            hp: 150,
             mana: 0,
             armor: 0,
             damage: 9,
-        },
-        mana_spent: 0,
-        active_effects: Vec::new(),
-        is_player_turn: true,
-        hard_mode: false,
-    };
+        };
+        GameState::new(player, boss, false)
+    }
 
-    let mut memo = HashMap::new();
-    if let Some(min_mana) = simulate_turn(initial_state, &spells, &mut memo) {
-        println!("Part 1: {}", min_mana);
-    } else {
-        println!("No solution found.");
+    #[test]
+    fn high_hp_boss() {
+        let spells = SpellBook::try_from(DEFAULT_SPELLS).unwrap().0;
+        let state = high_hp_state();
+        let solution = solve(state, &spells).unwrap();
+        assert_eq!(solution.mana_spent, 3010);
+        let sequence = solution
+            .spells
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            replay(state, &spells, &sequence),
+            Replay::Won {
+                mana_spent: 3010,
+                ..
+            }
+        ));
     }
 
-    let initial_state = GameState {
-        player: Character {
+    #[test]
+    fn matches_exhaustive_search() {
+        let spells = SpellBook::try_from(DEFAULT_SPELLS).unwrap().0;
+        let boss = Character::from("Hit Points: 51\nDamage: 9".to_string());
+        let player = Character {
             hp: 50,
             mana: 500,
             armor: 0,
             damage: 0,
+        };
+        for hard_mode in [false, true] {
+            let state = GameState::new(player, boss, hard_mode);
+            assert_eq!(
+                solve(state, &spells).map(|solution| solution.mana_spent),
+                cheapest_win_from(state, &spells, &mut HashMap::new())
+            );
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn high_hp_boss_matches_exhaustive_search() {
+        let spells = SpellBook::try_from(DEFAULT_SPELLS).unwrap().0;
+        let best = cheapest_win_from(high_hp_state(), &spells, &mut HashMap::new());
+        assert_eq!(best, Some(3010));
+    }
+
+    // Memoised depth-first search for the cheapest mana still needed to win from
+    // each state, as a cross-check independent of the Dijkstra search.
+    fn cheapest_win_from(
+        state: GameState,
+        spells: &[Spell],
+        memo: &mut HashMap<StateKey, Option<i32>>,
+    ) -> Option<i32> {
+        if let Some(&cheapest) = memo.get(&state.key()) {
+            return cheapest;
+        }
+        let mut next = state;
+        let cheapest = match begin_turn(&mut next, spells) {
+            Some(Outcome::Won) => Some(0),
+            Some(Outcome::Lost) => None,
+            None if next.is_player_turn => (0..spells.len())
+                .filter(|&spell| can_cast(&next, spells, spell))
+                .filter_map(|spell| {
+                    let mut after = next;
+                    cast(&mut after, spells, spell);
+                    let cost = spells[spell].cost;
+                    if after.boss.hp <= 0 {
+                        Some(cost)
+                    } else {
+                        cheapest_win_from(after, spells, memo).map(|rest| rest + cost)
+                    }
+                })
+                .min(),
+            None => {
+                boss_attack(&mut next);
+                if next.player.hp <= 0 {
+                    None
+                } else {
+                    cheapest_win_from(next, spells, memo)
+                }
+            }
+        };
+        memo.insert(state.key(), cheapest);
+        cheapest
+    }
+
+    #[test]
+    fn parse_boss() {
+        let boss = Character::from("Hit Points: 51\nDamage: 9".to_string());
+        assert_eq!(boss.hp, 51);
+        assert_eq!(boss.damage, 9);
+        assert_eq!(boss.armor, 0);
+    }
+
+    #[test]
+    fn interactive_play() {
+        let spells = SpellBook::try_from(DEFAULT_SPELLS).unwrap().0;
+        let input = "hint\nfireball\npoison\nPoison\n1\n";
+        let mut output = Vec::new();
+        let outcome = play(example_state(13), &spells, input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(outcome, Some(Outcome::Won));
+        assert!(output.contains("Hint: Poison, Magic Missile (costs 226 more mana)"));
+        assert!(output.contains("Unknown spell fireball"));
+        assert!(output.contains("Cannot cast Poison: the effect is still active"));
+        assert!(output.contains("Player casts Magic Missile."));
+        assert!(output.contains("The boss dies, you win!"));
+    }
+
+    #[test]
+    fn interactive_quit() {
+        let spells = SpellBook::try_from(DEFAULT_SPELLS).unwrap().0;
+        let mut output = Vec::new();
+        let outcome = play(example_state(13), &spells, "quit\n".as_bytes(), &mut output);
+        assert_eq!(outcome.unwrap(), None);
+    }
+
+    #[parameterized(
+        sequence = {
+            &["Poison", "Magic Missile"],
+            &["Magic Missile", "Magic Missile"],
+            &["Poison", "Poison"],
+            &["Poison", "Recharge"],
+            &["Poison", "Fireball"],
+            &["Poison"],
         },
-        boss: Character {
-            hp: 51,
-            mana: 0,
-            armor: 0,
-            damage: 9,
-        },
-        mana_spent: 0,
-        active_effects: Vec::new(),
-        is_player_turn: true,
-        hard_mode: true,
-    };
+        expected = {
+            Replay::Won { turn: 4, mana_spent: 226 },
+            Replay::Lost { turn: 4 },
+            Replay::Illegal { turn: 3, spell: "Poison".to_string(), error: CastError::AlreadyActive },
+            Replay::Illegal { turn: 3, spell: "Recharge".to_string(), error: CastError::NotEnoughMana },
+            Replay::Illegal { turn: 3, spell: "Fireball".to_string(), error: CastError::UnknownSpell },
+            Replay::Incomplete { turn: 3 },
+        }
+    )]
+    fn replay_sequences(sequence: &[&str], expected: Replay) {
+        let spells = SpellBook::try_from(DEFAULT_SPELLS).unwrap().0;
+        assert_eq!(replay(example_state(13), &spells, sequence), expected);
+    }
 
-    let mut memo = HashMap::new();
-    if let Some(min_mana) = simulate_turn(initial_state, &spells, &mut memo) {
-        println!("Part 2: {}", min_mana);
-    } else {
-        println!("No solution found.");
+    #[test]
+    fn replay_matches_solver() {
+        let spells = SpellBook::try_from(DEFAULT_SPELLS).unwrap().0;
+        let solution = solve(example_state(14), &spells).unwrap();
+        let sequence = solution
+            .spells
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            replay(example_state(14), &spells, &sequence),
+            Replay::Won {
+                turn: 10,
+                mana_spent: solution.mana_spent
+            }
+        );
     }
 }
@@ -1,29 +1,103 @@
+use std::{
+    fmt::Debug,
+    str::FromStr,
+    sync::mpsc::{channel, Receiver, Sender},
+};
+
 use common::read_input;
 
+trait Word: Copy + Debug + Default + PartialEq + FromStr {
+    fn from_usize(value: usize) -> Self;
+    fn is_zero(&self) -> bool;
+    fn is_one(&self) -> bool;
+    fn is_even(&self) -> bool;
+    fn is_positive(&self) -> bool;
+    fn add(self, other: Self) -> Self;
+    fn sub(self, other: Self) -> Self;
+    fn mul(self, other: Self) -> Self;
+    fn half(self) -> Self;
+}
+
+macro_rules! impl_word {
+    ($($t:ty),*) => {
+        $(
+            impl Word for $t {
+                fn from_usize(value: usize) -> Self {
+                    value as $t
+                }
+
+                fn is_zero(&self) -> bool {
+                    *self == 0
+                }
+
+                fn is_one(&self) -> bool {
+                    *self == 1
+                }
+
+                fn is_even(&self) -> bool {
+                    *self % 2 == 0
+                }
+
+                fn is_positive(&self) -> bool {
+                    *self > 0
+                }
+
+                fn add(self, other: Self) -> Self {
+                    self + other
+                }
+
+                fn sub(self, other: Self) -> Self {
+                    self - other
+                }
+
+                fn mul(self, other: Self) -> Self {
+                    self * other
+                }
+
+                fn half(self) -> Self {
+                    self / 2
+                }
+            }
+        )*
+    };
+}
+
+impl_word!(usize, i64);
+
+const REGISTERS: usize = 4;
+
 #[derive(Debug)]
-struct Computer {
-    reg_a: usize,
-    reg_b: usize,
+struct Computer<W: Word = usize> {
+    registers: [W; REGISTERS],
+    #[cfg(test)]
     instructions: Vec<Instruction>,
+    program: Program<W>,
 }
 
-impl From<String> for Computer {
+impl<W: Word> From<String> for Computer<W> {
     fn from(value: String) -> Self {
-        let instructions = value.lines().map(|line| Instruction::from(line)).collect();
+        let instructions = value.lines().map(Instruction::from).collect::<Vec<_>>();
+        let program = Program::from(instructions.as_slice()).fused();
         Self {
-            reg_a: 0,
-            reg_b: 0,
+            registers: [W::default(); REGISTERS],
+            #[cfg(test)]
             instructions,
+            program,
         }
     }
 }
 
-impl Computer {
-    fn with_reg_a(mut self, reg_a: usize) -> Self {
-        self.reg_a = reg_a;
+impl<W: Word> Computer<W> {
+    fn with_reg_a(self, reg_a: W) -> Self {
+        self.with_register("a", reg_a)
+    }
+
+    fn with_register(mut self, register: &str, value: W) -> Self {
+        self.registers[register_index(register)] = value;
         self
     }
 
+    #[cfg(test)]
     fn get_instruction(&self, index: isize) -> Option<&Instruction> {
         if index < 0 {
             None
@@ -32,104 +106,393 @@ impl Computer {
         }
     }
 
-    fn execute(&mut self) -> (usize, usize) {
+    fn execute(&mut self) -> (W, W) {
+        let (_, input) = channel();
+        let (output, _) = channel();
+        self.execute_with_io(&input, &output)
+    }
+
+    fn execute_with_io(&mut self, input: &Receiver<W>, output: &Sender<W>) -> (W, W) {
+        self.program.run(&mut self.registers, input, output);
+        (self.registers[0], self.registers[1])
+    }
+
+    #[cfg(test)]
+    fn register(&mut self, register: &str) -> &mut W {
+        &mut self.registers[register_index(register)]
+    }
+
+    #[cfg(test)]
+    fn value_of(&self, operand: &str) -> W {
+        match operand.parse() {
+            Ok(value) => value,
+            Err(_) => self.registers[register_index(operand)],
+        }
+    }
+
+    #[cfg(test)]
+    fn interpret(&mut self) -> (W, W) {
         let mut index = 0;
         while let Some(instruction) = self.get_instruction(index) {
-            dbg!(&self.reg_a, &self.reg_b);
-            dbg!(instruction);
-            index = match instruction {
+            index = match instruction.clone() {
                 Instruction::Hlf(reg) => {
-                    match reg.as_str() {
-                        "a" => self.reg_a /= 2,
-                        "b" => self.reg_b /= 2,
-                        r => panic!("Unknown register {r}"),
-                    }
+                    let r = self.register(&reg);
+                    *r = r.half();
                     index + 1
                 }
                 Instruction::Tpl(reg) => {
-                    match reg.as_str() {
-                        "a" => self.reg_a *= 3,
-                        "b" => self.reg_b *= 3,
-                        r => panic!("Unknown register {r}"),
-                    }
+                    let r = self.register(&reg);
+                    *r = r.mul(W::from_usize(3));
                     index + 1
                 }
                 Instruction::Inc(reg) => {
-                    match reg.as_str() {
-                        "a" => self.reg_a += 1,
-                        "b" => self.reg_b += 1,
-                        r => panic!("Unknown register {r}"),
-                    }
+                    let r = self.register(&reg);
+                    *r = r.add(W::from_usize(1));
+                    index + 1
+                }
+                Instruction::Dec(reg) => {
+                    let r = self.register(&reg);
+                    *r = r.sub(W::from_usize(1));
+                    index + 1
+                }
+                Instruction::Cpy(src, reg) => {
+                    *self.register(&reg) = self.value_of(&src);
+                    index + 1
+                }
+                Instruction::Add(src, reg) => {
+                    let value = self.value_of(&src);
+                    let r = self.register(&reg);
+                    *r = r.add(value);
+                    index + 1
+                }
+                Instruction::Sub(src, reg) => {
+                    let value = self.value_of(&src);
+                    let r = self.register(&reg);
+                    *r = r.sub(value);
+                    index + 1
+                }
+                Instruction::Mul(src, reg) => {
+                    let value = self.value_of(&src);
+                    let r = self.register(&reg);
+                    *r = r.mul(value);
                     index + 1
                 }
                 Instruction::Jmp(offset) => index + offset,
                 Instruction::Jie(reg, offset) => {
-                    let must_jump = match reg.as_str() {
-                        "a" => self.reg_a % 2 == 0,
-                        "b" => self.reg_b % 2 == 0,
-                        r => panic!("Unknown register {r}"),
-                    };
-                    if must_jump {
+                    if self.value_of(&reg).is_even() {
                         index + offset
                     } else {
                         index + 1
                     }
                 }
                 Instruction::Jio(reg, offset) => {
-                    let must_jump = match reg.as_str() {
-                        "a" => self.reg_a == 1,
-                        "b" => self.reg_b == 1,
-                        r => panic!("Unknown register {r}"),
-                    };
-                    if must_jump {
+                    if self.value_of(&reg).is_one() {
+                        index + offset
+                    } else {
+                        index + 1
+                    }
+                }
+                Instruction::Jz(src, offset) => {
+                    if self.value_of(&src).is_zero() {
+                        index + offset
+                    } else {
+                        index + 1
+                    }
+                }
+                Instruction::Jnz(src, offset) => {
+                    if !self.value_of(&src).is_zero() {
                         index + offset
                     } else {
                         index + 1
                     }
                 }
+                Instruction::Out(_) | Instruction::In(_) => {
+                    panic!("The reference interpreter has no I/O channels")
+                }
             };
         }
-        (self.reg_a, self.reg_b)
+        (self.registers[0], self.registers[1])
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 enum Instruction {
     Hlf(String),
     Tpl(String),
     Inc(String),
+    Dec(String),
+    Cpy(String, String),
+    Add(String, String),
+    Sub(String, String),
+    Mul(String, String),
     Jmp(isize),
     Jie(String, isize),
     Jio(String, isize),
+    Jz(String, isize),
+    Jnz(String, isize),
+    Out(String),
+    In(String),
 }
 
 impl From<&str> for Instruction {
     fn from(value: &str) -> Self {
         let (command, params) = value.split_once(' ').unwrap();
+        let pair = || {
+            let (first, second) = params.split_once(", ").unwrap();
+            (first.to_string(), second.to_string())
+        };
+        let jump = || {
+            let (first, offset) = params.split_once(", ").unwrap();
+            (first.to_string(), offset.parse().unwrap())
+        };
         match command {
             "hlf" => Instruction::Hlf(params.to_string()),
             "tpl" => Instruction::Tpl(params.to_string()),
             "inc" => Instruction::Inc(params.to_string()),
+            "dec" => Instruction::Dec(params.to_string()),
+            "cpy" => {
+                let (src, reg) = pair();
+                Instruction::Cpy(src, reg)
+            }
+            "add" => {
+                let (src, reg) = pair();
+                Instruction::Add(src, reg)
+            }
+            "sub" => {
+                let (src, reg) = pair();
+                Instruction::Sub(src, reg)
+            }
+            "mul" => {
+                let (src, reg) = pair();
+                Instruction::Mul(src, reg)
+            }
             "jmp" => Instruction::Jmp(params.parse().unwrap()),
             "jie" => {
-                let (register, offset) = params.split_once(", ").unwrap();
-                Instruction::Jie(register.to_string(), offset.parse().unwrap())
+                let (register, offset) = jump();
+                Instruction::Jie(register, offset)
             }
             "jio" => {
-                let (register, offset) = params.split_once(", ").unwrap();
-                Instruction::Jio(register.to_string(), offset.parse().unwrap())
+                let (register, offset) = jump();
+                Instruction::Jio(register, offset)
+            }
+            "jz" => {
+                let (src, offset) = jump();
+                Instruction::Jz(src, offset)
+            }
+            "jnz" => {
+                let (src, offset) = jump();
+                Instruction::Jnz(src, offset)
             }
+            "out" => Instruction::Out(params.to_string()),
+            "in" => Instruction::In(params.to_string()),
             c => panic!("Unknown command {c}"),
         }
     }
 }
 
+fn register_index(register: &str) -> usize {
+    match register {
+        "a" => 0,
+        "b" => 1,
+        "c" => 2,
+        "d" => 3,
+        r => panic!("Unknown register {r}"),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Value<W> {
+    Register(usize),
+    Literal(W),
+}
+
+impl<W: Word> From<&str> for Value<W> {
+    fn from(operand: &str) -> Self {
+        match operand.parse() {
+            Ok(value) => Value::Literal(value),
+            Err(_) => Value::Register(register_index(operand)),
+        }
+    }
+}
+
+impl<W: Word> Value<W> {
+    fn get(&self, registers: &[W; REGISTERS]) -> W {
+        match self {
+            Value::Register(reg) => registers[*reg],
+            Value::Literal(value) => *value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op<W> {
+    Hlf(usize),
+    Tpl(usize),
+    Inc(usize),
+    Dec(usize),
+    Cpy(Value<W>, usize),
+    Add(Value<W>, usize),
+    Sub(Value<W>, usize),
+    Mul(Value<W>, usize),
+    Jmp(isize),
+    Jie(usize, isize),
+    Jio(usize, isize),
+    Jz(Value<W>, isize),
+    Jnz(Value<W>, isize),
+    Out(Value<W>),
+    In(usize),
+    Collatz { value: usize, steps: usize },
+}
+
+#[derive(Debug)]
+struct Program<W> {
+    ops: Vec<Op<W>>,
+}
+
+impl<W: Word> From<&[Instruction]> for Program<W> {
+    fn from(instructions: &[Instruction]) -> Self {
+        let ops = instructions
+            .iter()
+            .map(|instruction| match instruction {
+                Instruction::Hlf(reg) => Op::Hlf(register_index(reg)),
+                Instruction::Tpl(reg) => Op::Tpl(register_index(reg)),
+                Instruction::Inc(reg) => Op::Inc(register_index(reg)),
+                Instruction::Dec(reg) => Op::Dec(register_index(reg)),
+                Instruction::Cpy(src, reg) => Op::Cpy(src.as_str().into(), register_index(reg)),
+                Instruction::Add(src, reg) => Op::Add(src.as_str().into(), register_index(reg)),
+                Instruction::Sub(src, reg) => Op::Sub(src.as_str().into(), register_index(reg)),
+                Instruction::Mul(src, reg) => Op::Mul(src.as_str().into(), register_index(reg)),
+                Instruction::Jmp(offset) => Op::Jmp(*offset),
+                Instruction::Jie(reg, offset) => Op::Jie(register_index(reg), *offset),
+                Instruction::Jio(reg, offset) => Op::Jio(register_index(reg), *offset),
+                Instruction::Jz(src, offset) => Op::Jz(src.as_str().into(), *offset),
+                Instruction::Jnz(src, offset) => Op::Jnz(src.as_str().into(), *offset),
+                Instruction::Out(src) => Op::Out(src.as_str().into()),
+                Instruction::In(reg) => Op::In(register_index(reg)),
+            })
+            .collect();
+        Self { ops }
+    }
+}
+
+impl<W: Word> Program<W> {
+    const COLLATZ_LEN: usize = 8;
+
+    // Recognises the loop
+    //   jio v, +8 / inc s / jie v, +4 / tpl v / inc v / jmp +2 / hlf v / jmp -7
+    // which counts in `s` the Collatz steps needed to bring `v` down to 1.
+    fn collatz_at(&self, index: usize) -> Option<Op<W>> {
+        let window = self.ops.get(index..index + Self::COLLATZ_LEN)?;
+        match *window {
+            [Op::Jio(v0, 8), Op::Inc(s), Op::Jie(v1, 4), Op::Tpl(v2), Op::Inc(v3), Op::Jmp(2), Op::Hlf(v4), Op::Jmp(-7)]
+                if s != v0 && [v1, v2, v3, v4].iter().all(|&v| v == v0) =>
+            {
+                Some(Op::Collatz {
+                    value: v0,
+                    steps: s,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn fused(mut self) -> Self {
+        // Only the loop head is replaced: the body stays in place so that jumps
+        // landing inside the loop keep their original meaning.
+        for index in 0..self.ops.len() {
+            if let Some(op) = self.collatz_at(index) {
+                self.ops[index] = op;
+            }
+        }
+        self
+    }
+
+    fn run(&self, registers: &mut [W; REGISTERS], input: &Receiver<W>, output: &Sender<W>) {
+        let jump_if = |condition: bool, index: isize, offset: isize| {
+            if condition {
+                index + offset
+            } else {
+                index + 1
+            }
+        };
+        let mut index = 0;
+        while let Some(op) = usize::try_from(index).ok().and_then(|i| self.ops.get(i)) {
+            index = match *op {
+                Op::Hlf(reg) => {
+                    registers[reg] = registers[reg].half();
+                    index + 1
+                }
+                Op::Tpl(reg) => {
+                    registers[reg] = registers[reg].mul(W::from_usize(3));
+                    index + 1
+                }
+                Op::Inc(reg) => {
+                    registers[reg] = registers[reg].add(W::from_usize(1));
+                    index + 1
+                }
+                Op::Dec(reg) => {
+                    registers[reg] = registers[reg].sub(W::from_usize(1));
+                    index + 1
+                }
+                Op::Cpy(src, reg) => {
+                    registers[reg] = src.get(registers);
+                    index + 1
+                }
+                Op::Add(src, reg) => {
+                    registers[reg] = registers[reg].add(src.get(registers));
+                    index + 1
+                }
+                Op::Sub(src, reg) => {
+                    registers[reg] = registers[reg].sub(src.get(registers));
+                    index + 1
+                }
+                Op::Mul(src, reg) => {
+                    registers[reg] = registers[reg].mul(src.get(registers));
+                    index + 1
+                }
+                Op::Jmp(offset) => index + offset,
+                Op::Jie(reg, offset) => jump_if(registers[reg].is_even(), index, offset),
+                Op::Jio(reg, offset) => jump_if(registers[reg].is_one(), index, offset),
+                Op::Jz(src, offset) => jump_if(src.get(registers).is_zero(), index, offset),
+                Op::Jnz(src, offset) => jump_if(!src.get(registers).is_zero(), index, offset),
+                Op::Out(src) => {
+                    // A host that stopped listening simply discards the output.
+                    let _ = output.send(src.get(registers));
+                    index + 1
+                }
+                Op::In(reg) => {
+                    registers[reg] = input.recv().expect("Input channel closed");
+                    index + 1
+                }
+                Op::Collatz { value, steps } => {
+                    let mut n = registers[value];
+                    if !n.is_positive() {
+                        panic!("Collatz loop never terminates with register value {n:?}");
+                    }
+                    let mut count = 0;
+                    while !n.is_one() {
+                        n = if n.is_even() {
+                            n.half()
+                        } else {
+                            n.mul(W::from_usize(3)).add(W::from_usize(1))
+                        };
+                        count += 1;
+                    }
+                    registers[value] = n;
+                    registers[steps] = registers[steps].add(W::from_usize(count));
+                    index + Self::COLLATZ_LEN as isize
+                }
+            };
+        }
+    }
+}
+
 fn main() {
     let input = read_input("day23.txt");
-    let mut computer = Computer::from(input.clone());
+    let mut computer = Computer::<usize>::from(input.clone());
     let (_reg_a, reg_b) = computer.execute();
     println!("Part 1 = {reg_b}");
-    let mut computer = Computer::from(input).with_reg_a(1);
+    let mut computer = Computer::<usize>::from(input).with_reg_a(1);
     let (_reg_a, reg_b) = computer.execute();
     println!("Part 2 = {reg_b}");
 }
@@ -144,8 +507,111 @@ mod day23_tests {
 jio a, +2
 tpl a
 inc a"#;
-        let mut computer = Computer::from(input.to_string());
+        let mut computer = Computer::<usize>::from(input.to_string());
         let (reg_a, _reg_b) = computer.execute();
         assert_eq!(reg_a, 2);
     }
+
+    const COLLATZ: &str = r#"jio a, +8
+inc b
+jie a, +4
+tpl a
+inc a
+jmp +2
+hlf a
+jmp -7"#;
+
+    #[test]
+    fn fuses_collatz_loop() {
+        let computer = Computer::<usize>::from(COLLATZ.to_string());
+        assert_eq!(computer.program.ops[0], Op::Collatz { value: 0, steps: 1 });
+        assert_eq!(computer.program.ops.len(), 8);
+    }
+
+    #[test]
+    fn fused_matches_interpreter() {
+        let input = format!("jio a, +3\ntpl a\ninc a\ninc a\n{COLLATZ}\ninc a");
+        for reg_a in 0..1000 {
+            let mut fused = Computer::<usize>::from(input.clone()).with_reg_a(reg_a);
+            let mut interpreted = Computer::<usize>::from(input.clone()).with_reg_a(reg_a);
+            assert_eq!(fused.execute(), interpreted.interpret());
+        }
+    }
+
+    #[test]
+    fn extended_instructions() {
+        let input = r#"cpy 5, c
+cpy 1, a
+mul c, a
+dec c
+jnz c, -2
+sub 20, a
+add a, b
+jz d, +2
+inc b"#;
+        let mut computer = Computer::<usize>::from(input.to_string());
+        assert_eq!(computer.execute(), (100, 100));
+        let mut computer = Computer::<usize>::from(input.to_string());
+        assert_eq!(computer.interpret(), (100, 100));
+    }
+
+    #[test]
+    fn signed_registers() {
+        let input = r#"sub 3, a
+cpy a, b
+mul -2, b"#;
+        let mut computer = Computer::<i64>::from(input.to_string());
+        assert_eq!(computer.execute(), (-3, 6));
+    }
+
+    #[test]
+    fn input_output_channels() {
+        let input = r#"in a
+jz a, +4
+mul a, a
+out a
+jmp -4
+out -1"#;
+        let (input_tx, input_rx) = channel();
+        let (output_tx, output_rx) = channel();
+        [2, -3, 4, 0]
+            .into_iter()
+            .for_each(|v| input_tx.send(v).unwrap());
+        let mut computer = Computer::<i64>::from(input.to_string());
+        computer.execute_with_io(&input_rx, &output_tx);
+        drop(output_tx);
+        assert_eq!(output_rx.iter().collect::<Vec<_>>(), vec![4, 9, 16, -1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Input channel closed")]
+    fn execute_without_input() {
+        let input = r#"out 1
+in a"#;
+        Computer::<i64>::from(input.to_string()).execute();
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_fused_vs_interpreter() {
+        use std::time::Instant;
+
+        let mut computer = Computer::<usize>::from(COLLATZ.to_string());
+        let mut run = |reg_a, fused: bool| {
+            computer.registers = [reg_a, 0, 0, 0];
+            if fused {
+                computer.execute().1
+            } else {
+                computer.interpret().1
+            }
+        };
+        let start = Instant::now();
+        let interpreted = (1..100_000).map(|reg_a| run(reg_a, false)).sum::<usize>();
+        let interpreted_time = start.elapsed();
+        let start = Instant::now();
+        let fused = (1..100_000).map(|reg_a| run(reg_a, true)).sum::<usize>();
+        let fused_time = start.elapsed();
+        println!("interpreter: {interpreted_time:?}, fused: {fused_time:?}");
+        assert_eq!(interpreted, fused);
+    }
 }
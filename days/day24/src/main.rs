@@ -1,64 +1,159 @@
-use std::usize;
+use std::collections::HashSet;
 
 use common::read_input;
 
-fn quantum_energy_of(presents: &[i64]) -> i64 {
-    presents.iter().fold(1, |qe, p| qe * p)
+fn quantum_energy_of(presents: &[u64]) -> Option<u128> {
+    presents
+        .iter()
+        .try_fold(1u128, |qe, &p| qe.checked_mul(p as u128))
 }
 
-fn combine(
-    presents: Vec<i64>,
-    group: Vec<i64>,
-    mut min_l: usize,
-    mut min_qe: i64,
-    target_sum: i64,
-) -> (usize, i64) {
-    if target_sum == 0 {
-        return (group.len(), quantum_energy_of(&group));
+type Mask = u128;
+
+struct Packages {
+    presents: Vec<u64>,
+    suffix_sums: Vec<u64>,
+    target_sum: u64,
+}
+
+impl Packages {
+    fn new(mut presents: Vec<u64>, num_groups: usize) -> Option<Self> {
+        if presents.len() > Mask::BITS as usize {
+            return None;
+        }
+        let total = presents.iter().sum::<u64>();
+        if num_groups == 0 || total % num_groups as u64 != 0 {
+            return None;
+        }
+        presents.sort_by(|a, b| b.cmp(a));
+        let mut suffix_sums = vec![0; presents.len() + 1];
+        (0..presents.len())
+            .rev()
+            .for_each(|i| suffix_sums[i] = suffix_sums[i + 1] + presents[i]);
+        Some(Self {
+            presents,
+            suffix_sums,
+            target_sum: total / num_groups as u64,
+        })
     }
 
-    if group.len() > min_l || (presents.is_empty() && target_sum > 0) {
-        return (usize::max_value(), i64::max_value());
+    fn all(&self) -> Mask {
+        Mask::MAX
+            .checked_shr(Mask::BITS - self.presents.len() as u32)
+            .unwrap_or(0)
     }
 
-    (0..presents.len()).for_each(|idx| {
-        let mut remaining = presents.to_vec();
-        let present = remaining.remove(idx);
-        let (current_l, current_qe) = if group.len() <= min_l && target_sum - present >= 0 {
-            let mut group_with_present = group.clone();
-            group_with_present.push(present);
-            combine(
-                remaining,
-                group_with_present,
-                min_l,
-                min_qe,
-                target_sum - present,
-            )
-        } else {
-            (usize::max_value(), i64::max_value())
-        };
-
-        if current_l == min_l {
-            min_qe = min_qe.min(current_qe);
-        } else if current_l < min_l {
-            min_l = current_l;
-            min_qe = current_qe;
-        };
-    });
-    (min_l, min_qe)
+    fn values(&self, group: Mask) -> Vec<u64> {
+        (0..self.presents.len())
+            .filter(|i| group & (1 << i) != 0)
+            .map(|i| self.presents[i])
+            .collect()
+    }
+
+    // Visits the groups made of `group` plus presents from `available` at index
+    // `start` or later that reach the target sum, with exactly `size` presents
+    // when given. Stops as soon as `visit` returns true.
+    fn visit_groups(
+        &self,
+        available: Mask,
+        size: Option<usize>,
+        start: usize,
+        group: Mask,
+        sum: u64,
+        visit: &mut dyn FnMut(Mask) -> bool,
+    ) -> bool {
+        if sum == self.target_sum && size.is_none_or(|size| size == 0) {
+            return visit(group);
+        }
+        if size == Some(0) {
+            return false;
+        }
+        for i in start..self.presents.len() {
+            // Presents are sorted descending: if everything left cannot reach
+            // the target, neither can any later start.
+            let reachable = match size {
+                Some(size) => {
+                    self.suffix_sums[i] - self.suffix_sums[(i + size).min(self.presents.len())]
+                }
+                None => self.suffix_sums[i],
+            };
+            if sum + reachable < self.target_sum {
+                break;
+            }
+            if available & (1 << i) == 0 || sum + self.presents[i] > self.target_sum {
+                continue;
+            }
+            if self.visit_groups(
+                available,
+                size.map(|size| size - 1),
+                i + 1,
+                group | (1 << i),
+                sum + self.presents[i],
+                visit,
+            ) {
+                return true;
+            }
+        }
+        false
+    }
+
+    // Splits `available` into groups of the target sum, remembering which
+    // bitmasks cannot be split.
+    fn split(&self, available: Mask, infeasible: &mut HashSet<Mask>) -> Option<Vec<Mask>> {
+        if available == 0 {
+            return Some(vec![]);
+        }
+        if infeasible.contains(&available) {
+            return None;
+        }
+        // The largest remaining present has to go in some group: only groups
+        // containing it are tried, which avoids exploring symmetric splits.
+        let first = available.trailing_zeros() as usize;
+        let mut split = None;
+        self.visit_groups(
+            available & !(1 << first),
+            None,
+            first + 1,
+            1 << first,
+            self.presents[first],
+            &mut |group| {
+                let Some(mut groups) = self.split(available & !group, infeasible) else {
+                    return false;
+                };
+                groups.insert(0, group);
+                split = Some(groups);
+                true
+            },
+        );
+        if split.is_none() {
+            infeasible.insert(available);
+        }
+        split
+    }
 }
 
-fn best_qe(presents: Vec<i64>, num_groups: i64) -> (usize, i64) {
-    let target_sum = presents.iter().sum::<i64>() / num_groups;
-    let mut presents = presents.clone();
-    presents.sort_by(|a, b| b.cmp(&a));
-    combine(
-        presents,
-        vec![],
-        usize::max_value(),
-        i64::max_value(),
-        target_sum,
-    )
+fn best_qe(presents: Vec<u64>, num_groups: usize) -> Option<Vec<Vec<u64>>> {
+    let packages = Packages::new(presents, num_groups)?;
+    let all = packages.all();
+    let mut infeasible = HashSet::new();
+    (1..=packages.presents.len()).find_map(|size| {
+        let mut candidates = Vec::new();
+        packages.visit_groups(all, Some(size), 0, 0, 0, &mut |group| {
+            candidates.push((quantum_energy_of(&packages.values(group)), group));
+            false
+        });
+        // Groups whose entanglement overflows sort last.
+        candidates.sort_by_key(|&(qe, _)| (qe.is_none(), qe));
+        candidates.into_iter().find_map(|(_, group)| {
+            let rest = packages.split(all & !group, &mut infeasible)?;
+            Some(
+                std::iter::once(group)
+                    .chain(rest)
+                    .map(|group| packages.values(group))
+                    .collect(),
+            )
+        })
+    })
 }
 
 fn main() {
@@ -66,9 +161,11 @@ fn main() {
     let presents = input
         .lines()
         .map(|line| line.parse().unwrap())
-        .collect::<Vec<i64>>();
-    println!("Part 1 = {}", best_qe(presents.clone(), 3).1);
-    println!("Part 2 = {}", best_qe(presents, 4).1);
+        .collect::<Vec<u64>>();
+    let groups = best_qe(presents.clone(), 3).unwrap();
+    println!("Part 1 = {}", quantum_energy_of(&groups[0]).unwrap());
+    let groups = best_qe(presents, 4).unwrap();
+    println!("Part 2 = {}", quantum_energy_of(&groups[0]).unwrap());
 }
 
 #[cfg(test)]
@@ -90,8 +187,12 @@ mod day24_tests {
         let presents = input
             .lines()
             .map(|line| line.parse().unwrap())
-            .collect::<Vec<i64>>();
-        assert_eq!(best_qe(presents, 3).1, 99);
+            .collect::<Vec<u64>>();
+        let groups = best_qe(presents, 3).unwrap();
+        assert_eq!(groups[0], vec![11, 9]);
+        assert_eq!(quantum_energy_of(&groups[0]), Some(99));
+        assert_eq!(groups.len(), 3);
+        assert!(groups.iter().all(|g| g.iter().sum::<u64>() == 20));
     }
 
     #[test]
@@ -109,7 +210,68 @@ mod day24_tests {
         let presents = input
             .lines()
             .map(|line| line.parse().unwrap())
-            .collect::<Vec<i64>>();
-        assert_eq!(best_qe(presents, 4).1, 44);
+            .collect::<Vec<u64>>();
+        let groups = best_qe(presents, 4).unwrap();
+        assert_eq!(quantum_energy_of(&groups[0]), Some(44));
+        assert_eq!(groups.len(), 4);
+        assert!(groups.iter().all(|g| g.iter().sum::<u64>() == 15));
+    }
+
+    #[test]
+    fn rejects_unbalanced_rest() {
+        // 12 + 10 + 8 has the lowest quantum entanglement of the three-present
+        // groups, but 11, 11, 11, 9, 9, 9 cannot be split into two groups of 30.
+        let presents = vec![12, 11, 11, 11, 10, 9, 9, 9, 8];
+        let groups = best_qe(presents, 3).unwrap();
+        assert_eq!(
+            groups,
+            vec![vec![11, 11, 8], vec![12, 9, 9], vec![11, 10, 9]]
+        );
+        assert_eq!(quantum_energy_of(&groups[0]), Some(968));
+    }
+
+    #[test]
+    fn no_valid_partition() {
+        assert_eq!(best_qe(vec![6, 5, 4, 3], 3), None);
+        assert_eq!(best_qe(vec![6, 5, 4], 4), None);
+        assert_eq!(best_qe(vec![], 3), None);
+    }
+
+    #[test]
+    fn many_packages() {
+        let presents = (1..=60).collect::<Vec<u64>>();
+        let groups = best_qe(presents.clone(), 3).unwrap();
+        assert_eq!(groups.len(), 3);
+        assert!(groups.iter().all(|g| g.iter().sum::<u64>() == 610));
+        let mut used = groups.concat();
+        used.sort();
+        assert_eq!(used, presents);
+        assert_eq!(groups[0].len(), 12);
+        assert!(quantum_energy_of(&groups[0]).unwrap() > i64::MAX as u128);
+
+        let groups = best_qe(presents, 5).unwrap();
+        assert_eq!(groups.len(), 5);
+        assert!(groups.iter().all(|g| g.iter().sum::<u64>() == 366));
+    }
+
+    #[test]
+    fn package_limit() {
+        let mut presents = vec![1; 127];
+        presents.push(127);
+        let groups = best_qe(presents.clone(), 2).unwrap();
+        assert_eq!(groups, vec![vec![127], vec![1; 127]]);
+
+        presents.push(2);
+        assert_eq!(presents.len(), 129);
+        assert_eq!(best_qe(presents, 2), None);
+    }
+
+    #[test]
+    fn overflowing_quantum_entanglement() {
+        assert_eq!(
+            quantum_energy_of(&[u64::MAX, u64::MAX]),
+            Some(u64::MAX as u128 * u64::MAX as u128)
+        );
+        assert_eq!(quantum_energy_of(&[u64::MAX, u64::MAX, 2]), None);
     }
 }
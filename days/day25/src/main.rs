@@ -1,29 +1,199 @@
+use std::{collections::HashMap, ops::RangeInclusive};
+
 fn get_code_number(row: usize, col: usize) -> u64 {
-    let number_after_rows = (1..=row).fold(1, |mut acc, row| {
-        acc += row as u64 - 1;
-        acc
-    });
-    let code_number = (2..=col).fold(number_after_rows, |mut acc, col| {
-        acc += (col + row) as u64 - 1;
-        acc
-    });
-    code_number
+    let diagonal = (row + col - 1) as u64;
+    diagonal * (diagonal - 1) / 2 + col as u64
 }
 
-fn get_code(starting: u64, row: usize, col: usize) -> u64 {
-    let number = get_code_number(row, col);
-    if number == 1 {
-        starting
-    } else {
-        (1..number).fold(starting, |mut acc, _n| {
-            acc = (acc * 252533) % 33554393;
-            acc
+fn get_position(code_number: u64) -> (usize, usize) {
+    // Smallest diagonal whose last code number is at least `code_number`.
+    let mut diagonal = ((8 * code_number + 1).isqrt() - 1) / 2;
+    while diagonal * (diagonal + 1) / 2 < code_number {
+        diagonal += 1;
+    }
+    let col = code_number - diagonal * (diagonal - 1) / 2;
+    let row = diagonal - col + 1;
+    (row as usize, col as usize)
+}
+
+fn mul_mod(a: u64, b: u64, modulus: u64) -> u64 {
+    ((a as u128 * b as u128) % modulus as u128) as u64
+}
+
+fn pow_mod(mut base: u64, mut exponent: u64, modulus: u64) -> u64 {
+    let mut result = 1 % modulus;
+    base %= modulus;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul_mod(result, base, modulus);
+        }
+        base = mul_mod(base, base, modulus);
+        exponent >>= 1;
+    }
+    result
+}
+
+fn inverse_mod(value: u64, modulus: u64) -> Option<u64> {
+    let (mut old_r, mut r) = (value as i128 % modulus as i128, modulus as i128);
+    let (mut old_s, mut s) = (1i128, 0i128);
+    while r != 0 {
+        let quotient = old_r / r;
+        (old_r, r) = (r, old_r - quotient * r);
+        (old_s, s) = (s, old_s - quotient * s);
+    }
+    (old_r == 1).then(|| old_s.rem_euclid(modulus as i128) as u64)
+}
+
+struct CodeGenerator {
+    starting: u64,
+    multiplier: u64,
+    modulus: u64,
+}
+
+impl CodeGenerator {
+    fn new(starting: u64) -> Self {
+        Self {
+            starting,
+            multiplier: 252533,
+            modulus: 33554393,
+        }
+    }
+
+    fn with_multiplier(mut self, multiplier: u64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    fn with_modulus(mut self, modulus: u64) -> Self {
+        self.modulus = modulus;
+        self
+    }
+
+    fn code_at(&self, row: usize, col: usize) -> u64 {
+        let number = get_code_number(row, col);
+        let factor = pow_mod(self.multiplier, number - 1, self.modulus);
+        mul_mod(self.starting, factor, self.modulus)
+    }
+
+    // Baby-step giant-step: finds the smallest k with starting * multiplier^k
+    // equal to `code`, and maps code number k + 1 back to its position.
+    fn position_of(&self, code: u64) -> Option<(usize, usize)> {
+        let modulus = self.modulus;
+        let target = mul_mod(code, inverse_mod(self.starting, modulus)?, modulus);
+        let step = (modulus as f64).sqrt().ceil() as u64;
+        let mut baby_steps = HashMap::new();
+        let mut value = 1 % modulus;
+        for j in 0..step {
+            baby_steps.entry(value).or_insert(j);
+            value = mul_mod(value, self.multiplier, modulus);
+        }
+        let giant_step = inverse_mod(pow_mod(self.multiplier, step, modulus), modulus)?;
+        let mut gamma = target;
+        for i in 0..step {
+            if let Some(j) = baby_steps.get(&gamma) {
+                return Some(get_position(i * step + j + 1));
+            }
+            gamma = mul_mod(gamma, giant_step, modulus);
+        }
+        None
+    }
+}
+
+enum GridFormat {
+    Table,
+    Csv,
+}
+
+fn render_grid(
+    generator: &CodeGenerator,
+    rows: RangeInclusive<usize>,
+    cols: RangeInclusive<usize>,
+    format: GridFormat,
+) -> String {
+    let codes = rows
+        .clone()
+        .map(|row| {
+            cols.clone()
+                .map(|col| generator.code_at(row, col).to_string())
+                .collect::<Vec<_>>()
         })
+        .collect::<Vec<_>>();
+    match format {
+        GridFormat::Csv => {
+            let header = cols.map(|col| format!(",{col}")).collect::<String>();
+            rows.zip(codes)
+                .map(|(row, codes)| format!("{row},{}\n", codes.join(",")))
+                .fold(format!("{header}\n"), |csv, line| csv + &line)
+        }
+        GridFormat::Table => {
+            let row_width = rows.end().to_string().len();
+            let cell_width = codes
+                .iter()
+                .flatten()
+                .map(|code| code.len())
+                .chain(std::iter::once(cols.end().to_string().len()))
+                .max()
+                .unwrap();
+            let header = cols
+                .clone()
+                .map(|col| format!("{col:>cell_width$}"))
+                .collect::<Vec<_>>()
+                .join("  ");
+            let separator = "-".repeat(header.len());
+            let mut table = format!("{:row_width$} | {header}\n", "");
+            table.push_str(&format!("{}-+-{separator}\n", "-".repeat(row_width)));
+            rows.zip(codes).for_each(|(row, codes)| {
+                let line = codes
+                    .iter()
+                    .map(|code| format!("{code:>cell_width$}"))
+                    .collect::<Vec<_>>()
+                    .join("  ");
+                table.push_str(&format!("{row:>row_width$} | {line}\n"));
+            });
+            table
+        }
     }
 }
 
+fn get_code(starting: u64, row: usize, col: usize) -> u64 {
+    CodeGenerator::new(starting).code_at(row, col)
+}
+
 fn main() {
     println!("Part 1 = {}", get_code(20151125, 2981, 3075));
+    let args = std::env::args().collect::<Vec<_>>();
+    let mut generator = CodeGenerator::new(20151125);
+    if let Some(idx) = args.iter().position(|arg| arg == "--multiplier") {
+        generator = generator.with_multiplier(args[idx + 1].parse().unwrap());
+    }
+    if let Some(idx) = args.iter().position(|arg| arg == "--modulus") {
+        generator = generator.with_modulus(args[idx + 1].parse().unwrap());
+    }
+    if let Some(idx) = args.iter().position(|arg| arg == "--grid") {
+        let range = |arg: &str| {
+            let (from, to) = arg.split_once('-').unwrap();
+            from.parse().unwrap()..=to.parse().unwrap()
+        };
+        let format = if args.iter().any(|arg| arg == "--csv") {
+            GridFormat::Csv
+        } else {
+            GridFormat::Table
+        };
+        let grid = render_grid(
+            &generator,
+            range(&args[idx + 1]),
+            range(&args[idx + 2]),
+            format,
+        );
+        print!("{grid}");
+    }
+    if let Some(idx) = args.iter().position(|arg| arg == "--find") {
+        let code = args[idx + 1].parse().unwrap();
+        match generator.position_of(code) {
+            Some((row, col)) => println!("Code {code} is at row {row}, column {col}"),
+            None => println!("Code {code} never appears"),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -40,4 +210,82 @@ mod day25_tests {
         let (row, col) = input;
         assert_eq!(get_code(20151125, row, col), value);
     }
+
+    #[parameterized(
+        input = {(1, 1), (2, 1), (1, 2), (3, 1), (2, 2), (1, 3), (6, 6), (2981, 3075)},
+        number = {1, 2, 3, 4, 5, 6, 61, 18331560}
+    )]
+    fn code_number(input: (usize, usize), number: u64) {
+        let (row, col) = input;
+        assert_eq!(get_code_number(row, col), number);
+        assert_eq!(get_position(number), input);
+    }
+
+    #[parameterized(
+        value = {20151125, 31916031, 18749137, 1601130, 27995004},
+        position = {(1, 1), (2, 1), (1, 2), (3, 3), (6, 6)}
+    )]
+    fn position_of_code(value: u64, position: (usize, usize)) {
+        assert_eq!(
+            CodeGenerator::new(20151125).position_of(value),
+            Some(position)
+        );
+    }
+
+    #[test]
+    fn position_of_repeated_code() {
+        // The sequence repeats before row 2981, column 3075, so the lookup finds
+        // an earlier occurrence of the same code.
+        let generator = CodeGenerator::new(20151125);
+        let code = generator.code_at(2981, 3075);
+        let (row, col) = generator.position_of(code).unwrap();
+        assert_eq!(generator.code_at(row, col), code);
+        assert!(get_code_number(row, col) < get_code_number(2981, 3075));
+    }
+
+    #[test]
+    fn configurable_generator() {
+        // Powers of 3 modulo 7: 1, 3, 2, 6, 4, 5, 1, ...
+        let generator = CodeGenerator::new(1).with_multiplier(3).with_modulus(7);
+        assert_eq!(generator.code_at(2, 2), 4);
+        assert_eq!(generator.position_of(5), Some((1, 3)));
+        assert_eq!(generator.position_of(0), None);
+        assert_eq!(
+            CodeGenerator::new(1)
+                .with_multiplier(2)
+                .with_modulus(7)
+                .position_of(3),
+            None
+        );
+    }
+
+    #[test]
+    fn render_table() {
+        let table = r#"  |        1         2         3
+--+-----------------------------
+1 | 20151125  18749137  17289845
+2 | 31916031  21629792  16929656
+"#;
+        assert_eq!(
+            render_grid(
+                &CodeGenerator::new(20151125),
+                1..=2,
+                1..=3,
+                GridFormat::Table
+            ),
+            table
+        );
+    }
+
+    #[test]
+    fn render_csv() {
+        let csv = r#",5,6
+5,9250759,31663883
+6,1534922,27995004
+"#;
+        assert_eq!(
+            render_grid(&CodeGenerator::new(20151125), 5..=6, 5..=6, GridFormat::Csv),
+            csv
+        );
+    }
 }